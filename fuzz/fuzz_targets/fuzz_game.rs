@@ -0,0 +1,27 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_chess::{move_generation::Movegen, perft::perft, Game};
+
+// Arbitrary FEN strings. Anything that parses into a legal position should survive move
+// generation, making and unmaking every move, and a shallow perft
+fuzz_target!(|data: &[u8]| {
+    let Ok(fen) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(mut game) = Game::new(fen) else {
+        return;
+    };
+    // Missing kings and pawns on the back rank parse fine, but perft rightly gives up on them
+    if game.board.assert_legal_position().is_err() {
+        return;
+    }
+    let Ok(moves) = game.board.gen_moves() else {
+        return;
+    };
+    for mov in moves {
+        game.make_move(mov);
+        game.unmake_move(mov);
+    }
+    perft(&mut game, 2, false, false);
+});
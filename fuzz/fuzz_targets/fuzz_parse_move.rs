@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_chess::Game;
+
+// Arbitrary move strings against the starting position
+fuzz_target!(|data: &[u8]| {
+    let Ok(r#move) = std::str::from_utf8(data) else {
+        return;
+    };
+    let mut game = Game::default();
+    if let Ok(mov) = game.parse_move(r#move) {
+        game.make_move(mov);
+        game.unmake_move(mov);
+    }
+});
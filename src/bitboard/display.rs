@@ -9,6 +9,7 @@ use std::fmt::Write;
 pub trait BitboardDisplay: Sized {
     fn from_algebraic(algebraic: &str) -> Result<Self, BitboardError>;
     fn to_algebraic(&self) -> Result<String, BitboardError>;
+    fn to_algebraic_list(&self) -> Vec<String>;
     fn display_bitboard(&self) -> Vec<String>;
 }
 
@@ -73,6 +74,10 @@ impl BitboardDisplay for Bitboard {
         }
         Ok(algebraic)
     }
+    // Every set square in algebraic notation, from a1 to h8
+    fn to_algebraic_list(&self) -> Vec<String> {
+        self.map(|square| square.to_algebraic().unwrap()).collect()
+    }
     fn display_bitboard(&self) -> Vec<String> {
         let mut board = [['.'; 8]; 8];
         for i in 0..64 {
@@ -1,9 +1,12 @@
 use std::{
+    collections::BTreeSet,
     fmt::{Display, Formatter, LowerHex},
-    ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Shl, Shr},
+    ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Index, Not, Shl, Shr},
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Bitboard(pub u64);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -65,6 +68,28 @@ impl Direction {
         Self::SoWeWe,
         Self::SoSoWe,
     ];
+    // The direction that undoes this one
+    pub const fn opposite(self) -> Self {
+        match self {
+            Self::North => Self::South,
+            Self::South => Self::North,
+            Self::East => Self::West,
+            Self::West => Self::East,
+            Self::NorthEast => Self::SouthWest,
+            Self::NorthWest => Self::SouthEast,
+            Self::SouthEast => Self::NorthWest,
+            Self::SouthWest => Self::NorthEast,
+            Self::NoNoEa => Self::SoSoWe,
+            Self::NoEaEa => Self::SoWeWe,
+            Self::SoEaEa => Self::NoWeWe,
+            Self::SoSoEa => Self::NoNoWe,
+            Self::NoNoWe => Self::SoSoEa,
+            Self::NoWeWe => Self::SoEaEa,
+            Self::SoWeWe => Self::NoEaEa,
+            Self::SoSoWe => Self::NoNoEa,
+        }
+    }
+
     pub const fn pawn_captures(color: Color) -> [Self; 2] {
         match color {
             Color::White => [Self::NorthEast, Self::NorthWest],
@@ -114,6 +139,26 @@ pub fn generate_knight_lookup() -> [Bitboard; 64] {
     lookup
 }
 
+pub fn generate_king_lookup() -> [Bitboard; 64] {
+    let mut lookup: [Bitboard; 64] = [Bitboard(0); 64];
+    let mut i: u8 = 0;
+    while i < 64 {
+        let square = Bitboard(1 << i);
+        // one step in all 8 directions
+        lookup[i as usize] = square.north()
+            | square.south()
+            | square.east()
+            | square.west()
+            | square.north_east()
+            | square.north_west()
+            | square.south_east()
+            | square.south_west();
+        i += 1;
+    }
+
+    lookup
+}
+
 pub trait DirectionalShift:
     Sized + Shl<u64, Output = Self> + Shr<u64, Output = Self> + BitAnd<Self, Output = Self>
 {
@@ -245,6 +290,30 @@ impl Bitboard {
         Self(0xFF_00_00_00_00_00_00_00),
     ];
 
+    // a1-h8 direction, indexed by `rank - file + 7`
+    pub const DIAGONAL_MASKS: [Self; 15] = {
+        let mut masks = [Self(0); 15];
+        let mut square = 0;
+        while square < 64 {
+            let idx = square / 8 + 7 - square % 8;
+            masks[idx] = Self(masks[idx].0 | 1 << square);
+            square += 1;
+        }
+        masks
+    };
+
+    // h1-a8 direction, indexed by `rank + file`
+    pub const ANTIDIAGONAL_MASKS: [Self; 15] = {
+        let mut masks = [Self(0); 15];
+        let mut square = 0;
+        while square < 64 {
+            let idx = square / 8 + square % 8;
+            masks[idx] = Self(masks[idx].0 | 1 << square);
+            square += 1;
+        }
+        masks
+    };
+
     pub const FILE_H: Self = Self::FILES[7];
     pub const NOT_FILE_H: Self = Self(0x7f_7f_7f_7f_7f_7f_7f_7f);
     pub const FILE_A: Self = Self::FILES[0];
@@ -255,9 +324,17 @@ impl Bitboard {
     pub const NOT_FILE_AB: Self = Self(0xfc_fc_fc_fc_fc_fc_fc_fc);
 
     pub const RANK_1: Self = Self::RANKS[0];
+    pub const RANK_2: Self = Self::RANKS[1];
+    pub const RANK_3: Self = Self::RANKS[2];
+    pub const RANK_6: Self = Self::RANKS[5];
+    pub const RANK_7: Self = Self::RANKS[6];
     pub const RANK_8: Self = Self::RANKS[7];
     pub const PAWN_PROMOTION_MASK: Self = Bitboard(Self::RANK_8.0 | Self::RANK_1.0);
 
+    // a1 is a dark square
+    pub const DARK_SQUARES: Self = Self(0xAA_55_AA_55_AA_55_AA_55);
+    pub const LIGHT_SQUARES: Self = Self(!Self::DARK_SQUARES.0);
+
     const PAWN_INITIAL: Self = Self(0x00_FF_00_00_00_00_FF_00);
     // Some day :(
     // pub const KING_INITIAL: Self = Self::from_algebraic("e1").unwrap() & Self::from_algebraic("e8").unwrap();
@@ -307,6 +384,228 @@ impl Bitboard {
     pub fn count(&self) -> usize {
         self.0.count_ones() as usize
     }
+
+    // Least significant set square, or an empty bitboard
+    #[inline(always)]
+    pub const fn lsb(&self) -> Self {
+        Self(self.0 & self.0.wrapping_neg())
+    }
+
+    // Removes the least significant set square and returns it
+    #[inline(always)]
+    pub fn pop_lsb(&mut self) -> Option<Self> {
+        if self.is_empty() {
+            return None;
+        }
+        let lsb = self.lsb();
+        self.0 &= self.0 - 1;
+        Some(lsb)
+    }
+
+    // Indices of every set square, in ascending order
+    pub fn to_square_list(&self) -> Vec<u8> {
+        self.map(|square| square.idx() as u8).collect()
+    }
+
+    pub fn to_square_set(&self) -> BTreeSet<u8> {
+        self.map(|square| square.idx() as u8).collect()
+    }
+
+    // File of the least significant set square
+    #[inline(always)]
+    pub fn file_mask(self) -> Self {
+        Self::FILES[self.idx() % 8]
+    }
+
+    // Rank of the least significant set square
+    #[inline(always)]
+    pub fn rank_mask(self) -> Self {
+        Self::RANKS[self.idx() / 8]
+    }
+
+    // Diagonal (a1-h8 direction) through the least significant set square
+    #[inline(always)]
+    pub fn diagonal_of(self) -> Self {
+        let idx = self.idx();
+        Self::DIAGONAL_MASKS[idx / 8 + 7 - idx % 8]
+    }
+
+    // Anti-diagonal (h1-a8 direction) through the least significant set square
+    #[inline(always)]
+    pub fn antidiagonal_of(self) -> Self {
+        let idx = self.idx();
+        Self::ANTIDIAGONAL_MASKS[idx / 8 + idx % 8]
+    }
+
+    // Hyperbola quintessence: o ^ (o - 2r) finds the attacks towards the most significant bits,
+    // and doing the same on the bit-reversed board finds the other direction. `line` is the rank,
+    // file or diagonal through `square`
+    #[inline(always)]
+    fn line_attacks(occupancy: Self, square: Self, line: Self) -> Self {
+        let line = line.0 & !square.0;
+        let forward = occupancy.0 & line;
+        let reverse = forward.reverse_bits();
+        let forward = forward.wrapping_sub(square.0);
+        let reverse = reverse.wrapping_sub(square.0.reverse_bits());
+        Self((forward ^ reverse.reverse_bits()) & line)
+    }
+
+    #[inline(always)]
+    pub fn o_xray_attacks_horizontal(occupancy: Self, square: Self) -> Self {
+        Self::line_attacks(occupancy, square, square.rank_mask())
+    }
+
+    #[inline(always)]
+    pub fn o_xray_attacks_vertical(occupancy: Self, square: Self) -> Self {
+        Self::line_attacks(occupancy, square, square.file_mask())
+    }
+
+    #[inline(always)]
+    pub fn o_xray_attacks_diagonal(occupancy: Self, square: Self) -> Self {
+        Self::line_attacks(occupancy, square, square.diagonal_of())
+    }
+
+    #[inline(always)]
+    pub fn o_xray_attacks_antidiagonal(occupancy: Self, square: Self) -> Self {
+        Self::line_attacks(occupancy, square, square.antidiagonal_of())
+    }
+
+    // Half of `line_attacks`, towards the most significant bits when `upwards`
+    #[inline(always)]
+    fn half_line_attacks(occupancy: Self, square: Self, line: Self, upwards: bool) -> Self {
+        let below = Self(square.0 - 1);
+        let half = if upwards { !(below | square) } else { below };
+        Self::line_attacks(occupancy, square, line) & half
+    }
+
+    // Single ray attacks, up to and including the first blocker in `occupancy`
+
+    #[inline(always)]
+    pub fn ray_attacks_north(square: Self, occupancy: Self) -> Self {
+        Self::half_line_attacks(occupancy, square, square.file_mask(), true)
+    }
+
+    #[inline(always)]
+    pub fn ray_attacks_south(square: Self, occupancy: Self) -> Self {
+        Self::half_line_attacks(occupancy, square, square.file_mask(), false)
+    }
+
+    #[inline(always)]
+    pub fn ray_attacks_east(square: Self, occupancy: Self) -> Self {
+        Self::half_line_attacks(occupancy, square, square.rank_mask(), true)
+    }
+
+    #[inline(always)]
+    pub fn ray_attacks_west(square: Self, occupancy: Self) -> Self {
+        Self::half_line_attacks(occupancy, square, square.rank_mask(), false)
+    }
+
+    #[inline(always)]
+    pub fn ray_attacks_north_east(square: Self, occupancy: Self) -> Self {
+        Self::half_line_attacks(occupancy, square, square.diagonal_of(), true)
+    }
+
+    #[inline(always)]
+    pub fn ray_attacks_south_west(square: Self, occupancy: Self) -> Self {
+        Self::half_line_attacks(occupancy, square, square.diagonal_of(), false)
+    }
+
+    #[inline(always)]
+    pub fn ray_attacks_north_west(square: Self, occupancy: Self) -> Self {
+        Self::half_line_attacks(occupancy, square, square.antidiagonal_of(), true)
+    }
+
+    #[inline(always)]
+    pub fn ray_attacks_south_east(square: Self, occupancy: Self) -> Self {
+        Self::half_line_attacks(occupancy, square, square.antidiagonal_of(), false)
+    }
+
+    // Dumb7fill: the set squares plus every square they slide to through `empty` in `direction`,
+    // all pieces at once. Shifting the result once more adds the first blocker of each ray
+    pub fn fill_direction(self, empty: Self, direction: Direction) -> Self {
+        let mut fill = self;
+        let mut front = self;
+        while !front.is_empty() {
+            front = front.shift(direction) & empty;
+            fill |= front;
+        }
+        fill
+    }
+
+    // Squares strictly between `a` and `b` when they share a rank, file or diagonal, empty
+    // otherwise
+    pub fn between(a: Self, b: Self) -> Self {
+        if a == b {
+            return Self(0);
+        }
+        for line in [
+            a.rank_mask(),
+            a.file_mask(),
+            a.diagonal_of(),
+            a.antidiagonal_of(),
+        ] {
+            if line.intersects(b) {
+                return Self::line_attacks(b, a, line) & Self::line_attacks(a, b, line);
+            }
+        }
+        Self(0)
+    }
+
+    // The whole line through `a` and `b`, from edge to edge and both squares included, when they
+    // share a rank, file or diagonal. Empty otherwise
+    pub fn ray(a: Self, b: Self) -> Self {
+        if a == b {
+            return Self(0);
+        }
+        for direction in Direction::SLIDING_MOVES {
+            let forward = a.fill_direction(Self::MAX, direction);
+            if forward.intersects(b) {
+                return forward | a.fill_direction(Self::MAX, direction.opposite());
+            }
+        }
+        Self(0)
+    }
+
+    #[inline(always)]
+    pub fn adjacent_files_mask(self) -> Self {
+        let file = self.file_mask();
+        file.east() | file.west()
+    }
+
+    // Smear every set bit towards rank 8
+    #[inline(always)]
+    pub const fn north_fill(self) -> Self {
+        let mut fill = self.0;
+        fill |= fill << 8;
+        fill |= fill << 16;
+        fill |= fill << 32;
+        Self(fill)
+    }
+
+    // Smear every set bit towards rank 1
+    #[inline(always)]
+    pub const fn south_fill(self) -> Self {
+        let mut fill = self.0;
+        fill |= fill >> 8;
+        fill |= fill >> 16;
+        fill |= fill >> 32;
+        Self(fill)
+    }
+
+    // Mirror the board between ranks 1 and 8
+    #[inline(always)]
+    pub const fn flip_vertical(self) -> Self {
+        Self(self.0.swap_bytes())
+    }
+
+    // Every square strictly in front of the set squares, from `color`'s point of view
+    #[inline(always)]
+    pub fn front_span(self, color: Color) -> Self {
+        match color {
+            Color::White => self.north().north_fill(),
+            Color::Black => self.south().south_fill(),
+        }
+    }
 }
 
 impl DirectionalShift for Bitboard {
@@ -390,6 +689,23 @@ impl BitXor for Bitboard {
     }
 }
 
+// `bitboard[idx]` tells whether the square with that index is set. Panics past h8, like a slice
+impl Index<usize> for Bitboard {
+    type Output = bool;
+
+    fn index(&self, idx: usize) -> &bool {
+        assert!(
+            idx < 64,
+            "Square index {idx} out of range, squares go up to 63"
+        );
+        if (self.0 >> idx) & 1 == 1 {
+            &true
+        } else {
+            &false
+        }
+    }
+}
+
 impl LowerHex for Bitboard {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let val = self.0;
@@ -398,17 +714,13 @@ impl LowerHex for Bitboard {
     }
 }
 
-// TODO: This is slow as fuck
+// Iterating consumes the squares of the copy being iterated, not of the original bitboard.
+// `pop_lsb` makes that explicit
 impl Iterator for Bitboard {
     type Item = Bitboard;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.0 == 0 {
-            return None;
-        }
-        let lsb = self.0.trailing_zeros();
-        self.0 &= self.0 - 1;
-        Some(Bitboard(1 << lsb))
+        self.pop_lsb()
     }
 }
 
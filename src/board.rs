@@ -1,19 +1,57 @@
 #![allow(dead_code, unused_imports)]
 
 use std::fmt::{Display, Formatter, LowerHex, Result};
-use std::ops::{BitAnd, BitAndAssign, BitOrAssign, BitXorAssign, Not};
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXorAssign, Not};
 
 use crate::bitboard::display::BitboardDisplay;
-use crate::bitboard::{generate_knight_lookup, generate_pawn_lookup, Direction};
+use crate::bitboard::{
+    generate_king_lookup, generate_knight_lookup, generate_pawn_lookup, Direction,
+};
+use crate::move_generation::error::MovegenError;
 use crate::move_generation::Movegen;
 
 use crate::{
     bitboard::{Bitboard, DirectionalShift},
+    magic,
     piece::{to_letter, Color, Kind, Piece},
     r#move::Move,
+    zobrist::ZOBRIST,
 };
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BoardError {
+    // The piece a move left behind isn't there
+    PieceMismatch {
+        expected: Piece,
+        found: Option<Piece>,
+    },
+    SquareOccupied(Bitboard),
+}
+
+impl Display for BoardError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            Self::PieceMismatch {
+                expected,
+                found: Some(found),
+            } => write!(f, "Expected {expected}, found {found}"),
+            Self::PieceMismatch {
+                expected,
+                found: None,
+            } => write!(f, "Expected {expected}, found an empty square"),
+            Self::SquareOccupied(square) => write!(
+                f,
+                "Square {} is occupied",
+                square.to_algebraic().unwrap_or_else(|_| "EE".to_string())
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BoardError {}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OnePerColor<T> {
     pub white: T,
     pub black: T,
@@ -32,6 +70,8 @@ impl<T> OnePerColor<T> {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct CastlingRights(u8);
 
 impl CastlingRights {
@@ -62,6 +102,38 @@ impl CastlingRights {
         self & right != Self::NONE
     }
 
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    // White's rights become Black's and the other way around
+    fn swap_colors(self) -> Self {
+        Self((self.0 & Self::WHITE_BOTH.0) >> 2 | (self.0 & Self::BLACK_BOTH.0) << 2)
+    }
+
+    // Right tied to a rook on `square`, the one that goes away once anything moves from or to
+    // that square. None off the rooks' starting squares
+    pub fn rights_for_rook_square(square: Bitboard) -> Self {
+        const CORNERS: [(Bitboard, CastlingRights); 4] = [
+            (Bitboard(1 << 0), CastlingRights::WHITE_QUEENSIDE),
+            (Bitboard(1 << 7), CastlingRights::WHITE_KINGSIDE),
+            (Bitboard(1 << 56), CastlingRights::BLACK_QUEENSIDE),
+            (Bitboard(1 << 63), CastlingRights::BLACK_KINGSIDE),
+        ];
+        let mut lost = Self::NONE;
+        for (corner, rights) in CORNERS {
+            if square.intersects(corner) {
+                lost |= rights;
+            }
+        }
+        lost
+    }
+
+    // A rook leaving its starting square takes its side's right with it
+    pub fn update_on_rook_move(&mut self, from: Bitboard) {
+        self.set_castling_right(Self::rights_for_rook_square(from), false);
+    }
+
     #[inline(always)]
     pub const fn white_queenside_squares() -> Bitboard {
         Bitboard(0xe)
@@ -89,6 +161,13 @@ impl BitAnd<CastlingRights> for CastlingRights {
     }
 }
 
+impl BitOr for CastlingRights {
+    type Output = CastlingRights;
+    fn bitor(self, rhs: Self) -> Self {
+        CastlingRights(self.0 | rhs.0)
+    }
+}
+
 impl BitOrAssign for CastlingRights {
     fn bitor_assign(&mut self, rhs: Self) {
         self.0 |= rhs.0;
@@ -122,9 +201,15 @@ impl LowerHex for CastlingRights {
     }
 }
 
+fn pawn_attacks_lookup() -> OnePerColor<[Bitboard; 64]> {
+    let [white, black] = generate_pawn_lookup();
+    OnePerColor::new(white, black)
+}
+
 // Little-endian rank-file mapping
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Board {
     pub pawns: Bitboard,
     pub knights: Bitboard,
@@ -141,17 +226,26 @@ pub struct Board {
     pub en_passant: Option<Bitboard>,
 
     pub attacked_squares: Bitboard,
+    // Lookup tables are rebuilt on deserialization instead of being stored
+    #[cfg_attr(feature = "serde", serde(skip, default = "pawn_attacks_lookup"))]
     pub pawn_attacks_lookup: OnePerColor<[Bitboard; 64]>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "generate_knight_lookup"))]
     pub knight_attacks_lookup: [Bitboard; 64],
+    #[cfg_attr(feature = "serde", serde(skip, default = "generate_king_lookup"))]
+    pub king_attacks_lookup: [Bitboard; 64],
 
     pub castling: CastlingRights,
+
+    // Kept up to date by every method that changes the position. Assigning `turn`, `en_passant`
+    // or `castling` directly needs a `refresh_hash` afterwards
+    pub zobrist_hash: u64,
 }
 
 impl Board {
     pub fn new() -> Self {
-        let pawn_attacks_lookup = generate_pawn_lookup();
+        let pawn_attacks_lookup = pawn_attacks_lookup();
         let knight_attacks_lookup = generate_knight_lookup();
-        let pawn_attacks_lookup = OnePerColor::new(pawn_attacks_lookup[0], pawn_attacks_lookup[1]);
+        let king_attacks_lookup = generate_king_lookup();
         Self {
             pawns: Bitboard(0),
             knights: Bitboard(0),
@@ -166,20 +260,72 @@ impl Board {
             attacked_squares: Bitboard(0),
             pawn_attacks_lookup,
             knight_attacks_lookup,
+            king_attacks_lookup,
             castling: CastlingRights(0),
+            zobrist_hash: 0,
 
             turn: Color::White,
         }
     }
 
-    pub fn king_position(&self, color: Color) -> usize {
-        match color {
-            Color::White => self.king_position.white.expect("King position not set"),
-            Color::Black => self.king_position.black.expect("King position not set"),
-        }
+    // The initial position, without going through FEN parsing
+    pub fn starting_position() -> Self {
+        let mut board = Self {
+            pawns: Bitboard(0x00_FF_00_00_00_00_FF_00),
+            knights: Bitboard(0x42_00_00_00_00_00_00_42),
+            bishops: Bitboard(0x24_00_00_00_00_00_00_24),
+            rooks: Bitboard(0x81_00_00_00_00_00_00_81),
+            queens: Bitboard(0x08_00_00_00_00_00_00_08),
+            kings: Bitboard(0x10_00_00_00_00_00_00_10),
+            white: Bitboard(0x00_00_00_00_00_00_FF_FF),
+            black: Bitboard(0xFF_FF_00_00_00_00_00_00),
+            king_position: OnePerColor::new(Some(4), Some(60)),
+            castling: CastlingRights::ALL,
+            ..Self::new()
+        };
+        board.refresh_hash();
+        board
     }
 
-    pub fn get_color(self, square: Bitboard) -> Option<Color> {
+    // The same position with the colors swapped and the board mirrored vertically, so every
+    // evaluation term should come out the same for the side to move
+    pub fn swap_colors(&self) -> Self {
+        // Flipping ranks maps square i to i ^ 56
+        let flip_square = |idx: Option<usize>| idx.map(|idx| idx ^ 56);
+        let mut board = Self {
+            pawns: self.pawns.flip_vertical(),
+            knights: self.knights.flip_vertical(),
+            bishops: self.bishops.flip_vertical(),
+            rooks: self.rooks.flip_vertical(),
+            queens: self.queens.flip_vertical(),
+            kings: self.kings.flip_vertical(),
+            white: self.black.flip_vertical(),
+            black: self.white.flip_vertical(),
+            turn: !self.turn,
+            king_position: OnePerColor::new(
+                flip_square(self.king_position.black),
+                flip_square(self.king_position.white),
+            ),
+            en_passant: self.en_passant.map(Bitboard::flip_vertical),
+            attacked_squares: self.attacked_squares.flip_vertical(),
+            castling: self.castling.swap_colors(),
+            ..*self
+        };
+        board.refresh_hash();
+        board
+    }
+
+    pub fn king_position(&self, color: Color) -> std::result::Result<usize, MovegenError> {
+        (*self.king_position.get(color)).ok_or(MovegenError::KingNotFound(color))
+    }
+
+    // Whether the side to move is in check. A side without a king is never in check
+    pub fn in_check(&self) -> bool {
+        self.is_check(self.turn).unwrap_or(false)
+    }
+
+    // Color of the piece standing on `square`, if any
+    pub fn color_of_square(&self, square: Bitboard) -> Option<Color> {
         if !(square & self.white).is_empty() {
             Some(Color::White)
         } else if !(square & self.black).is_empty() {
@@ -189,15 +335,22 @@ impl Board {
         }
     }
 
+    #[deprecated(note = "renamed to `color_of_square`")]
+    pub fn get_color(self, square: Bitboard) -> Option<Color> {
+        self.color_of_square(square)
+    }
+
     #[inline(always)]
     pub fn anything(&self) -> Bitboard {
         self.black | self.white
     }
 
+    pub fn is_square_occupied(&self, square: Bitboard) -> bool {
+        self.anything().intersects(square)
+    }
+
     pub fn get_piece(&self, square: Bitboard) -> Option<Piece> {
-        let Some(color) = self.get_color(square) else {
-            return None;
-        };
+        let color = self.color_of_square(square)?;
         if !(square & self.pawns).is_empty() {
             Some(Piece::new(color, Kind::Pawn, square))
         } else if !(square & self.knights).is_empty() {
@@ -268,23 +421,29 @@ impl Board {
             );
         }
         color_mask.clear_bit(piece.position);
-        match piece.kind {
-            Kind::Pawn => self.pawns.clear_bit(piece.position),
-            Kind::Knight => self.knights.clear_bit(piece.position),
-            Kind::Bishop => self.bishops.clear_bit(piece.position),
-            Kind::Rook => self.rooks.clear_bit(piece.position),
-            Kind::Queen => self.queens.clear_bit(piece.position),
-            Kind::King => {
-                self.kings.clear_bit(piece.position);
-                match piece.color {
-                    Color::White => self.king_position.white = None,
-                    Color::Black => self.king_position.black = None,
-                }
+        self.piece_bitboard_mut(piece.kind)
+            .clear_bit(piece.position);
+        self.zobrist_hash ^= ZOBRIST.piece(piece);
+        if piece.kind == Kind::King {
+            match piece.color {
+                Color::White => self.king_position.white = None,
+                Color::Black => self.king_position.black = None,
             }
         }
     }
 
-    fn get_pieces(&self, kind: Kind, color: Color) -> Bitboard {
+    const fn piece_bitboard_mut(&mut self, kind: Kind) -> &mut Bitboard {
+        match kind {
+            Kind::Pawn => &mut self.pawns,
+            Kind::Knight => &mut self.knights,
+            Kind::Bishop => &mut self.bishops,
+            Kind::Rook => &mut self.rooks,
+            Kind::Queen => &mut self.queens,
+            Kind::King => &mut self.kings,
+        }
+    }
+
+    pub fn get_pieces(&self, kind: Kind, color: Color) -> Bitboard {
         let pieces = match kind {
             Kind::Pawn => self.pawns,
             Kind::Knight => self.knights,
@@ -302,16 +461,42 @@ impl Board {
         pieces & color_mask
     }
 
-    fn generate_pawn_attacks(&self, color: Color) -> Bitboard {
-        let mut attacks = Bitboard(0);
-        let pawns = self.get_pieces(Kind::Pawn, !color);
-        // find all pawns
-        for pawn in pawns {
-            let pawn_idx = pawn.idx();
-            let pawn_attack = self.pawn_attacks_lookup.get(color)[pawn_idx];
-            attacks |= pawn_attack;
+    // Every square attacked by `color`'s pawns, shifting all of them at once
+    pub fn pawn_attacks_all(&self, color: Color) -> Bitboard {
+        let pawns = self.get_pieces(Kind::Pawn, color);
+        match color {
+            Color::White => pawns.north_east() | pawns.north_west(),
+            Color::Black => pawns.south_east() | pawns.south_west(),
         }
-        attacks
+    }
+
+    // Squares a knight of `color` can jump to, empty or holding an enemy piece, from where it
+    // would attack two or more enemy pieces other than pawns
+    pub fn knight_fork_squares(&self, color: Color) -> Bitboard {
+        let targets = self.get_color_mask(!color) & !self.pawns;
+        let mut reachable = Bitboard(0);
+        for knight in self.get_pieces(Kind::Knight, color) {
+            reachable |= self.knight_attacks_lookup[knight.idx()];
+        }
+        let mut forks = Bitboard(0);
+        for square in reachable & !self.get_color_mask(color) {
+            if (self.knight_attacks_lookup[square.idx()] & targets).count() >= 2 {
+                forks |= square;
+            }
+        }
+        forks
+    }
+
+    // Whether an enemy pawn attacks `square`, `color` being the side that gets attacked. The
+    // lookup for `!color` holds the squares `!color`'s pawns would attack `square` from
+    pub fn attacked_by_pawn(&self, square: Bitboard, color: Color) -> bool {
+        (self.pawn_attacks_lookup.get(!color)[square.idx()] & self.get_pieces(Kind::Pawn, !color))
+            != Bitboard(0)
+    }
+
+    pub fn attacked_by_knight(&self, square: Bitboard, color: Color) -> bool {
+        (self.knight_attacks_lookup[square.idx()] & self.get_pieces(Kind::Knight, !color))
+            != Bitboard(0)
     }
 
     fn generate_knight_attacks(&self, color: Color) -> Bitboard {
@@ -325,226 +510,1209 @@ impl Board {
         attacks
     }
 
-    fn generate_bishop_attacks(&self, _color: Color) -> Bitboard {
-        // let moves: Vec<Move> = Vec::new();
-        // let bishop = self.get_piece(Kind::Bishop, !color);
-        // self.gen_sliding_moves(&mut moves, bishop, bishop, Direction::NorthEast);
-        // self.gen_sliding_moves(&mut moves, bishop, bishop, Direction::NorthWest);
-        // self.gen_sliding_moves(&mut moves, bishop, bishop, Direction::SouthEast);
-        // self.gen_sliding_moves(&mut moves, bishop, bishop, Direction::SouthWest);
-        // moves
-        Bitboard(0)
+    fn generate_bishop_attacks(&self, color: Color) -> Bitboard {
+        let mut attacks = Bitboard(0);
+        let bishops = self.get_pieces(Kind::Bishop, !color);
+        let occupancy = self.anything();
+        for bishop in bishops {
+            attacks |= Self::ray_attacks(bishop, &Direction::DIAGONAL_MOVES, occupancy);
+        }
+        attacks
+    }
+
+    // Squares attacked by every bishop, rook and queen of `!color`
+    pub fn generate_slider_attacks(&self, color: Color) -> Bitboard {
+        let mut attacks = self.generate_bishop_attacks(color);
+        let occupancy = self.anything();
+        for rook in self.get_pieces(Kind::Rook, !color) {
+            attacks |= Self::ray_attacks(rook, &Direction::STRAIGHT_MOVES, occupancy);
+        }
+        for queen in self.get_pieces(Kind::Queen, !color) {
+            attacks |= Self::ray_attacks(queen, &Direction::SLIDING_MOVES, occupancy);
+        }
+        attacks
+    }
+
+    // Squares attacked by `!color`, `color`'s king can't step on any of them. The king is left
+    // out of the occupancy so it can't hide from a slider on the squares behind itself
+    pub fn generate_king_danger_squares(&self, color: Color) -> Bitboard {
+        let enemy = self.get_color_mask(!color);
+        let occupancy = self.anything() & !(self.kings & self.get_color_mask(color));
+        let mut danger = self.pawn_attacks_all(!color) | self.generate_knight_attacks(color);
+        if let Ok(king_idx) = self.king_position(!color) {
+            danger |= self.king_attacks_lookup[king_idx];
+        }
+        for slider in (self.bishops | self.queens) & enemy {
+            danger |= Self::bishop_attacks_hq(slider, occupancy);
+        }
+        for slider in (self.rooks | self.queens) & enemy {
+            danger |= Self::rook_attacks_hq(slider, occupancy);
+        }
+        danger
+    }
+
+    // Squares reached from `square` along each direction, up to and including the first occupied
+    // square
+    pub fn ray_attacks(
+        square: Bitboard,
+        directions: &[Direction],
+        occupancy: Bitboard,
+    ) -> Bitboard {
+        let mut attacks = Bitboard(0);
+        for &direction in directions {
+            let mut current = square.shift(direction);
+            while !current.is_empty() {
+                attacks |= current;
+                if current.intersects(occupancy) {
+                    break;
+                }
+                current = current.shift(direction);
+            }
+        }
+        attacks
+    }
+
+    // Slider attacks through hyperbola quintessence. No lookup tables, so they also work as a
+    // reference for other slider implementations
+    pub fn rook_attacks_hq(square: Bitboard, occupancy: Bitboard) -> Bitboard {
+        Self::rook_attacks_on_rank(square, occupancy)
+            | Self::rook_attacks_on_file(square, occupancy)
+    }
+
+    // o - 2r along the rank of `rook_sq`, plus the same on the reversed board for the other side
+    pub fn rook_attacks_on_rank(rook_sq: Bitboard, occupancy: Bitboard) -> Bitboard {
+        Bitboard::o_xray_attacks_horizontal(occupancy, rook_sq)
+    }
+
+    pub fn rook_attacks_on_file(rook_sq: Bitboard, occupancy: Bitboard) -> Bitboard {
+        Bitboard::o_xray_attacks_vertical(occupancy, rook_sq)
+    }
+
+    pub fn bishop_attacks_hq(square: Bitboard, occupancy: Bitboard) -> Bitboard {
+        Bitboard::o_xray_attacks_diagonal(occupancy, square)
+            | Bitboard::o_xray_attacks_antidiagonal(occupancy, square)
+    }
+
+    pub fn queen_attacks_hq(square: Bitboard, occupancy: Bitboard) -> Bitboard {
+        Self::rook_attacks_hq(square, occupancy) | Self::bishop_attacks_hq(square, occupancy)
+    }
+
+    // Squares a rook, bishop or queen on `square` attacks with the given occupancy, whatever the
+    // implementation behind it. Panics for any other kind
+    pub fn sliding_attacks_from(square: Bitboard, kind: Kind, occupancy: Bitboard) -> Bitboard {
+        match kind {
+            Kind::Rook => magic::rook_attacks(square.idx(), occupancy),
+            Kind::Bishop => magic::bishop_attacks(square.idx(), occupancy),
+            Kind::Queen => magic::queen_attacks(square.idx(), occupancy),
+            _ => panic!("{kind:?} is not a sliding piece"),
+        }
+    }
+
+    // Every square the piece attacks or defends, regardless of what's standing on it
+    pub fn generate_attacks_for_piece(&self, piece: Piece) -> Bitboard {
+        self.attacks_with_occupancy(piece, self.anything())
+    }
+
+    // Same as `generate_attacks_for_piece`, but sliders are blocked by `occupancy` instead of the
+    // current position. Useful to look at the board as it will be after a move
+    pub fn attacks_with_occupancy(&self, piece: Piece, occupancy: Bitboard) -> Bitboard {
+        let square = piece.position;
+        match piece.kind {
+            // The lookup for a color holds the attacks of the opposite color's pawns
+            Kind::Pawn => self.pawn_attacks_lookup.get(!piece.color)[square.idx()],
+            Kind::Knight => self.knight_attacks_lookup[square.idx()],
+            Kind::Bishop => Self::ray_attacks(square, &Direction::DIAGONAL_MOVES, occupancy),
+            Kind::Rook => Self::ray_attacks(square, &Direction::STRAIGHT_MOVES, occupancy),
+            Kind::Queen => Self::ray_attacks(square, &Direction::SLIDING_MOVES, occupancy),
+            Kind::King => self.king_attacks_lookup[square.idx()],
+        }
     }
 
     fn calculate_attacked_squares(&self) -> Bitboard {
         let mut attacks = Bitboard(0);
         // pawns
-        attacks |= self.generate_pawn_attacks(self.turn);
+        attacks |= self.pawn_attacks_all(!self.turn);
 
         // knights
         attacks |= self.generate_knight_attacks(self.turn);
 
-        // bishops
-        // rooks
-        // queens
+        // bishops, rooks and queens
+        attacks |= self.generate_slider_attacks(self.turn);
+
         // king
         attacks
     }
 
-    pub fn flip_turn(&mut self) {
-        self.turn = !self.turn;
+    // Pieces of `color` attacked by at least one opponent piece
+    pub fn threatened_pieces(&self, color: Color) -> Bitboard {
+        let mut threatened = Bitboard(0);
+        for square in self.get_color_mask(color) {
+            if self.is_attacked(square, square.idx(), color) {
+                threatened |= square;
+            }
+        }
+        threatened
     }
 
-    pub fn move_piece(&mut self, mov: Move) {
-        #[cfg(debug_assertions)]
-        {
-            assert!(
-                self.get_piece(mov.from).is_some(),
-                "No piece found at origin square for move {mov}\n{self}",
-            );
-        }
-        let piece = mov.what;
-        if let Some(en_passant) = mov.en_passant {
-            self.en_passant = Some(en_passant);
-        } else {
-            self.en_passant = None;
+    // Empty squares right in front of `color`'s pawns
+    pub fn generate_pawn_push_targets(&self, color: Color) -> Bitboard {
+        let pawns = self.get_pieces(Kind::Pawn, color);
+        let empty = !self.anything();
+        match color {
+            Color::White => pawns.north() & empty,
+            Color::Black => pawns.south() & empty,
         }
+    }
 
-        if let Some(castle_move) = mov.castle_move {
-            // TODO: move it instead
-            self.clear_piece(Piece::new(piece.color, Kind::Rook, castle_move.0));
-            self.spawn_piece(Piece::new(piece.color, Kind::Rook, castle_move.1));
-            self.castling.toggle_right(mov.castling_rights_change);
+    // Empty squares two steps in front of `color`'s pawns that haven't moved yet, with nothing in
+    // between
+    pub fn generate_pawn_double_push_targets(&self, color: Color) -> Bitboard {
+        let single_pushes = self.generate_pawn_push_targets(color);
+        let empty = !self.anything();
+        match color {
+            Color::White => (single_pushes & Bitboard::RANK_3).north() & empty,
+            Color::Black => (single_pushes & Bitboard::RANK_6).south() & empty,
         }
+    }
 
-        // We handle capture first, so we don't face issues when trying to eat a piece of the same
-        // type
-        if let Some(capture) = mov.capture {
-            self.clear_piece(capture);
+    // Pieces of either color attacking `square`, whatever stands on it
+    pub fn attacks_to(&self, square: Bitboard) -> Bitboard {
+        let idx = square.idx();
+        let occupancy = self.anything();
+        // The lookup for a color holds the attacks of the opposite color's pawns, the squares
+        // that color's pawns would attack `square` from
+        let pawns = self.pawn_attacks_lookup.get(Color::White)[idx] & self.white
+            | self.pawn_attacks_lookup.get(Color::Black)[idx] & self.black;
+        pawns & self.pawns
+            | self.knight_attacks_lookup[idx] & self.knights
+            | self.king_attacks_lookup[idx] & self.kings
+            | Self::bishop_attacks_hq(square, occupancy) & (self.bishops | self.queens)
+            | Self::rook_attacks_hq(square, occupancy) & (self.rooks | self.queens)
+    }
+
+    // For every square, how many white pieces attack it minus how many black ones do. One
+    // `attacks_to` per square, so keep it out of hot paths
+    pub fn control_map(&self) -> [i8; 64] {
+        let mut control = [0; 64];
+        for (idx, entry) in control.iter_mut().enumerate() {
+            let attackers = self.attacks_to(Bitboard(1 << idx));
+            *entry =
+                (attackers & self.white).count() as i8 - (attackers & self.black).count() as i8;
         }
+        control
+    }
 
-        let color_mask = match piece.color {
-            Color::White => &mut self.white,
-            Color::Black => &mut self.black,
+    // Enemy pieces giving check to `color`'s king
+    pub fn checkers(&self, color: Color) -> Bitboard {
+        let Ok(king_idx) = self.king_position(color) else {
+            return Bitboard(0);
         };
+        self.attacks_to(Bitboard(1 << king_idx)) & self.get_color_mask(!color)
+    }
 
-        match piece.kind {
-            Kind::Pawn => {
-                self.pawns.move_bit(mov.from, mov.to);
-                // TODO: make promotions
-            }
-            Kind::Knight => {
-                self.knights.move_bit(mov.from, mov.to);
+    // Prints the board, then a map with `square` marked as `o` and its attackers as `x`
+    pub fn print_attacks(&self, square: Bitboard) {
+        let attackers = self.attacks_to(square);
+        println!("{self}");
+        for rank in (0..8).rev() {
+            let mut line = String::new();
+            for file in 0..8 {
+                let current = Bitboard::from_square(file, rank);
+                line += if current == square {
+                    "o "
+                } else if current.intersects(attackers) {
+                    "x "
+                } else {
+                    ". "
+                };
             }
-            Kind::Bishop => {
-                self.bishops.move_bit(mov.from, mov.to);
+            println!("{line}");
+        }
+        for attacker in attackers {
+            if let Some(piece) = self.get_piece(attacker) {
+                println!(
+                    "{piece} on {}",
+                    attacker.to_algebraic().unwrap_or_else(|_| "EE".to_string())
+                );
             }
-            Kind::Rook => {
-                self.rooks.move_bit(mov.from, mov.to);
+        }
+    }
+
+    // Prints the legal moves of the piece on `square`. Only the side to move has any
+    pub fn print_legal_moves_for(&self, square: Bitboard) {
+        let moves = self.gen_legal_moves().unwrap_or_default();
+        for mov in moves.iter().filter(|mov| mov.from == square) {
+            println!("{}", mov.lan_string());
+        }
+    }
+
+    // Pseudo-legal moves `color` would have if it were their turn. The en passant target only
+    // counts for the side to move
+    pub fn generate_moves_for_color(
+        &self,
+        color: Color,
+    ) -> std::result::Result<Vec<Move>, MovegenError> {
+        self.king_position(color)?;
+        let mut board = *self;
+        if board.turn != color {
+            board.flip_turn();
+            board.set_en_passant(None);
+        }
+        Ok(board.generate_moves_from_square_bitboard(board.get_color_mask(color)))
+    }
+
+    // FEN with this board's pieces and side to move, and the rest of the fields as given
+    pub fn fen_snapshot(
+        &self,
+        halfmove: u8,
+        fullmove: u16,
+        en_passant: Option<Bitboard>,
+        castling: CastlingRights,
+    ) -> String {
+        let mut fen = String::new();
+        for rank in (0..8).rev() {
+            let mut empty_squares = 0;
+            for file in 0..8 {
+                match self.get_piece(Bitboard::from_square(file, rank)) {
+                    Some(piece) => {
+                        if empty_squares > 0 {
+                            fen.push_str(&empty_squares.to_string());
+                            empty_squares = 0;
+                        }
+                        fen.push(to_letter(Some(piece)));
+                    }
+                    None => empty_squares += 1,
+                }
             }
-            Kind::Queen => {
-                self.queens.move_bit(mov.from, mov.to);
+            if empty_squares > 0 {
+                fen.push_str(&empty_squares.to_string());
             }
-            Kind::King => {
-                self.kings.move_bit(mov.from, mov.to);
-                match piece.color {
-                    Color::White => self.king_position.white = Some(mov.to.idx()),
-                    Color::Black => self.king_position.black = Some(mov.to.idx()),
-                }
-                // self.castling &= !(1 << mov.to.idx());
+            if rank > 0 {
+                fen.push('/');
             }
         }
-        color_mask.move_bit(mov.from, mov.to);
 
-        // self.attacked_squares = self.calculate_attacked_squares();
+        fen.push(' ');
+        fen.push(self.turn.to_fen_char());
 
-        #[cfg(debug_assertions)]
-        {
-            self.assert_sync();
+        fen.push(' ');
+        let castling_len = fen.len();
+        for (right, c) in [
+            (CastlingRights::WHITE_KINGSIDE, 'K'),
+            (CastlingRights::WHITE_QUEENSIDE, 'Q'),
+            (CastlingRights::BLACK_KINGSIDE, 'k'),
+            (CastlingRights::BLACK_QUEENSIDE, 'q'),
+        ] {
+            if castling.get_castling_right(right) {
+                fen.push(c);
+            }
+        }
+        if fen.len() == castling_len {
+            fen.push('-');
         }
-    }
 
-    pub fn assert_sync(&self) {
-        // verify that color masks are correct
-        assert_eq!(
-            self.white & self.black,
-            Bitboard(0),
-            "White and black overlap {} {}",
-            self.white,
-            self.black
-        );
-        // TODO: check that inter-piece masks dont collide, and always intersect with color_masks
+        fen.push(' ');
+        match en_passant.map(|square| square.to_algebraic()) {
+            Some(Ok(square)) => fen.push_str(&square),
+            _ => fen.push('-'),
+        }
+
+        fen.push_str(&format!(" {halfmove} {fullmove}"));
+        fen
     }
 
-    pub fn unmove_piece(&mut self, mov: Move) {
-        self.move_piece(Move::new(mov.to, mov.from, mov.what));
-        // restore old piece
-        if let Some(captured_piece) = mov.capture {
-            self.spawn_piece(captured_piece);
+    // Standalone SVG of the board from White's side, pieces as Unicode symbols and files and ranks
+    // labelled along the edges
+    #[cfg(feature = "svg")]
+    pub fn to_svg_string(&self) -> String {
+        use std::fmt::Write;
+
+        const SQUARE: usize = 45;
+        const MARGIN: usize = 20;
+        const SIZE: usize = SQUARE * 8 + MARGIN;
+        let symbol = |piece: Piece| match (piece.color, piece.kind) {
+            (Color::White, Kind::Pawn) => '♙',
+            (Color::White, Kind::Knight) => '♘',
+            (Color::White, Kind::Bishop) => '♗',
+            (Color::White, Kind::Rook) => '♖',
+            (Color::White, Kind::Queen) => '♕',
+            (Color::White, Kind::King) => '♔',
+            (Color::Black, Kind::Pawn) => '♟',
+            (Color::Black, Kind::Knight) => '♞',
+            (Color::Black, Kind::Bishop) => '♝',
+            (Color::Black, Kind::Rook) => '♜',
+            (Color::Black, Kind::Queen) => '♛',
+            (Color::Black, Kind::King) => '♚',
+        };
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{SIZE}\" height=\"{SIZE}\" viewBox=\"0 0 {SIZE} {SIZE}\">\n"
+        );
+        for rank in 0..8 {
+            // Rank 8 at the top
+            let y = (7 - rank) * SQUARE;
+            for file in 0..8 {
+                let x = MARGIN + file * SQUARE;
+                let fill = if (rank + file) % 2 == 0 {
+                    "#b58863"
+                } else {
+                    "#f0d9b5"
+                };
+                let _ = writeln!(
+                    svg,
+                    "<rect x=\"{x}\" y=\"{y}\" width=\"{SQUARE}\" height=\"{SQUARE}\" fill=\"{fill}\"/>"
+                );
+                let square = Bitboard::from_square(file as u8, rank as u8);
+                if let Some(piece) = self.get_piece(square) {
+                    let _ = writeln!(
+                        svg,
+                        "<text x=\"{}\" y=\"{}\" font-size=\"36\" text-anchor=\"middle\">{}</text>",
+                        x + SQUARE / 2,
+                        y + SQUARE - 9,
+                        symbol(piece)
+                    );
+                }
+            }
+            let _ = writeln!(
+                svg,
+                "<text x=\"{}\" y=\"{}\" font-size=\"14\" text-anchor=\"middle\">{}</text>",
+                MARGIN / 2,
+                y + SQUARE / 2 + 5,
+                rank + 1
+            );
+        }
+        for (file, letter) in ('a'..='h').enumerate() {
+            let _ = writeln!(
+                svg,
+                "<text x=\"{}\" y=\"{}\" font-size=\"14\" text-anchor=\"middle\">{letter}</text>",
+                MARGIN + file * SQUARE + SQUARE / 2,
+                SIZE - 5
+            );
         }
+        svg.push_str("</svg>");
+        svg
+    }
 
-        if let Some(castle_move) = mov.castle_move {
-            // TODO: move it instead
-            self.clear_piece(Piece::new(mov.what.color, Kind::Rook, castle_move.1));
-            self.spawn_piece(Piece::new(mov.what.color, Kind::Rook, castle_move.0));
-            self.castling.toggle_right(mov.castling_rights_change);
+    // Whether `mov` can be played here, for moves that come from somewhere other than this
+    // position's move generation, like the transposition table. Only the moving piece's moves
+    // get generated
+    pub fn is_legal_move(&self, mov: Move) -> bool {
+        if mov.what.color != self.turn || !self.gen_moves_from_piece(mov.from).contains(&mov) {
+            return false;
         }
+        let mut after = *self;
+        after.move_piece(mov);
+        !after.is_check(self.turn).unwrap_or(true)
     }
 
-    pub fn spawn_piece(&mut self, piece: Piece) {
-        let color_mask = match piece.color {
-            Color::White => &mut self.white,
-            Color::Black => &mut self.black,
+    // Same as `gen_legal_moves().len()`, but counts each piece's targets instead of generating
+    // and playing the moves. Pins and checks come from the pin rays and the checkmask, and only
+    // en passant, which can uncover a check along the rank, is played out
+    pub fn legal_moves_count(&self) -> usize {
+        let color = self.turn;
+        let Ok(king_idx) = self.king_position(color) else {
+            return 0;
         };
+        let own = self.get_color_mask(color);
+        let enemy = self.get_color_mask(!color);
+        let occupancy = self.anything();
 
-        let position = piece.position;
-
-        #[cfg(debug_assertions)]
-        {
-            assert!(
-                !color_mask.intersects(piece.position),
-                "Tried to spawn a piece on a busy square\n {} {} {}",
-                self,
-                piece.position,
-                piece
+        let king = Bitboard(1 << king_idx);
+        let king_targets =
+            self.king_attacks_lookup[king_idx] & !own & !self.generate_king_danger_squares(color);
+        let mut count = king_targets.count();
+        let checkmask = self.checkmask(color);
+        if checkmask.is_empty() {
+            // Double check, only the king can move
+            return count;
+        }
+        if king.intersects(Bitboard::KING_INITIAL) {
+            let mut castles = vec![];
+            self.gen_castling_moves(
+                &mut castles,
+                Piece::new(color, Kind::King, king),
+                king,
+                color,
             );
+            count += castles.len();
         }
 
-        color_mask.set_bit(position);
-        match piece.kind {
-            Kind::Pawn => {
-                self.pawns.set_bit(position);
-            }
-            Kind::Knight => {
-                self.knights.set_bit(position);
-            }
-            Kind::Bishop => {
-                self.bishops.set_bit(position);
-            }
-            Kind::Rook => {
-                self.rooks.set_bit(position);
+        let pins = self.pin_rays(color);
+        let pin_ray = |square: Bitboard| {
+            pins.iter()
+                .find(|(pinned, _)| pinned.intersects(square))
+                .map_or(Bitboard::MAX, |&(_, ray)| ray)
+        };
+        let allowed = |square: Bitboard| !own & checkmask & pin_ray(square);
+
+        for knight in self.get_pieces(Kind::Knight, color) {
+            count += (self.knight_attacks_lookup[knight.idx()] & allowed(knight)).count();
+        }
+        for slider in (self.bishops | self.rooks | self.queens) & own {
+            let kind = self
+                .get_piece(slider)
+                .map_or(Kind::Queen, |piece| piece.kind);
+            let attacks = Self::sliding_attacks_from(slider, kind, occupancy);
+            count += (attacks & allowed(slider)).count();
+        }
+
+        let push_targets = self.generate_pawn_push_targets(color);
+        let double_push_targets = self.generate_pawn_double_push_targets(color);
+        for pawn in self.get_pieces(Kind::Pawn, color) {
+            let single = match color {
+                Color::White => pawn.north(),
+                Color::Black => pawn.south(),
+            } & push_targets;
+            let double = match color {
+                Color::White => single.north(),
+                Color::Black => single.south(),
+            } & double_push_targets;
+            // The lookup for `!color` holds the squares a `color` pawn attacks
+            let captures = self.pawn_attacks_lookup.get(!color)[pawn.idx()] & enemy;
+            let targets = (single | double | captures) & allowed(pawn);
+            // Each promotion is four moves
+            count += (targets & !Bitboard::PAWN_PROMOTION_MASK).count()
+                + (targets & Bitboard::PAWN_PROMOTION_MASK).count() * 4;
+        }
+        if let Some(en_passant_square) = self.en_passant {
+            let victim = self.get_en_passant_victim(en_passant_square, !color);
+            let attackers = self.pawn_attacks_lookup.get(color)[en_passant_square.idx()]
+                & self.get_pieces(Kind::Pawn, color);
+            for from in attackers {
+                let mov = Move::new(from, en_passant_square, Piece::new(color, Kind::Pawn, from))
+                    .with_capture(victim);
+                let mut after = *self;
+                after.move_piece(mov);
+                if !after.is_check(color).unwrap_or(true) {
+                    count += 1;
+                }
             }
-            Kind::Queen => {
-                self.queens.set_bit(position);
+        }
+        count
+    }
+
+    // Squares a piece other than the king has to move to, or capture on, to get `color` out of
+    // check. Everything when not in check, nothing in double check
+    pub fn checkmask(&self, color: Color) -> Bitboard {
+        let checkers = self.checkers(color);
+        match checkers.count() {
+            0 => Bitboard::MAX,
+            1 => self.generate_check_blocks(checkers, color) | checkers,
+            _ => Bitboard(0),
+        }
+    }
+
+    // For each direction in `Direction::SLIDING_MOVES` order, the `color` piece pinned to its king
+    // along that ray, and the squares it can still move to: the ray up to and including the
+    // pinning slider. Both are empty when nothing is pinned that way
+    pub fn pin_rays(&self, color: Color) -> [(Bitboard, Bitboard); 8] {
+        let mut pins = [(Bitboard(0), Bitboard(0)); 8];
+        let Ok(king_idx) = self.king_position(color) else {
+            return pins;
+        };
+        let king = Bitboard(1 << king_idx);
+        let own = self.get_color_mask(color);
+        let enemy = self.get_color_mask(!color);
+        let occupancy = self.anything();
+        for (pin, direction) in pins.iter_mut().zip(Direction::SLIDING_MOVES) {
+            let sliders = if Direction::STRAIGHT_MOVES.contains(&direction) {
+                self.rooks | self.queens
+            } else {
+                self.bishops | self.queens
+            };
+            let blocker = Self::ray_attacks(king, &[direction], occupancy) & own;
+            if blocker.is_empty() {
+                continue;
             }
-            Kind::King => {
-                self.kings.set_bit(position);
-                match piece.color {
-                    Color::White => self.king_position.white = Some(position.idx()),
-                    Color::Black => self.king_position.black = Some(position.idx()),
-                }
-                #[cfg(debug_assertions)]
-                {
-                    assert!(
-                        (self.kings & *color_mask).count() == 1,
-                        "{} {} {}",
-                        self.kings,
-                        *color_mask,
-                        (self.kings & *color_mask).count()
-                    );
-                    assert!(
-                        self.king_position.get(piece.color).unwrap() == position.idx(),
-                        "King position out of sync with king"
-                    );
-                }
+            let xray = Self::ray_attacks(king, &[direction], occupancy & !blocker);
+            if (xray & !blocker).intersects(sliders & enemy) {
+                *pin = (blocker, xray);
             }
         }
+        pins
     }
 
-    pub fn get_color_mask(&self, color: Color) -> Bitboard {
-        match color {
-            Color::White => self.white,
-            Color::Black => self.black,
+    // Every `color` piece that can't leave the line between its king and an enemy slider
+    pub fn pinned_pieces(&self, color: Color) -> Bitboard {
+        self.pin_rays(color)
+            .iter()
+            .fold(Bitboard(0), |pinned, &(piece, _)| pinned | piece)
+    }
+
+    // Squares the piece on `pinned_sq` can move to without exposing its king: the pin ray up to
+    // and including the pinning slider. The whole board when it isn't pinned or there's no piece
+    pub fn pin_ray(&self, pinned_sq: usize) -> Bitboard {
+        let square = Bitboard(1 << pinned_sq);
+        let Some(piece) = self.get_piece(square) else {
+            return Bitboard::MAX;
+        };
+        self.pin_rays(piece.color)
+            .into_iter()
+            .find(|(pinned, _)| pinned.intersects(square))
+            .map_or(Bitboard::MAX, |(_, ray)| ray)
+    }
+
+    // Squares between `color`'s king and `checker_sq` where a piece can interpose. Checks from
+    // knights and pawns can't be blocked
+    pub fn generate_check_blocks(&self, checker_sq: Bitboard, color: Color) -> Bitboard {
+        if !checker_sq.intersects(self.bishops | self.rooks | self.queens) {
+            return Bitboard(0);
         }
+        Bitboard::between(self.get_pieces(Kind::King, color), checker_sq)
     }
-}
 
-impl Default for Board {
-    fn default() -> Self {
-        Self::new()
+    // A piece is hanging when it's attacked by the opponent and not defended by its own side
+    pub fn is_piece_hanging(&self, square: Bitboard, color: Color) -> bool {
+        let idx = square.idx();
+        self.is_attacked(square, idx, color) && !self.is_attacked(square, idx, !color)
     }
-}
 
-pub fn colorize(letter: char) -> String {
-    // if check
-    let answer: String = if letter.is_ascii_uppercase() {
-        format!("\x1b[0;31m{}\x1b[0m", letter)
-    } else {
-        format!("\x1b[0;34m{}\x1b[0m", letter)
-    };
-    answer
-}
+    // An outpost can never be chased away by an enemy pawn and is defended by one of our own
+    pub fn is_outpost(&self, square: Bitboard, color: Color) -> bool {
+        let enemy_pawns = self.get_pieces(Kind::Pawn, !color);
+        let span = (square.file_mask() | square.adjacent_files_mask()).front_span(color)
+            & square.rank_mask().front_span(color);
+        let supporters =
+            self.pawn_attacks_lookup.get(color)[square.idx()] & self.get_pieces(Kind::Pawn, color);
 
-impl Display for Board {
-    fn fmt(&self, f: &mut Formatter) -> Result {
-        let mut board = String::new();
-        for rank in (0..8).rev() {
-            for file in 0..8 {
-                let square = Bitboard::from_square(file, rank);
-                let piece = self.get_piece(square);
-                match piece {
-                    Some(piece) => {
-                        board += &format!("{} ", colorize(to_letter(Some(piece))));
+        !span.intersects(enemy_pawns) && !supporters.is_empty()
+    }
+
+    // No enemy pawn in front of it on its own or an adjacent file can stop or capture it
+    pub fn is_passed_pawn(&self, square: Bitboard, color: Color) -> bool {
+        let span = (square | square.east() | square.west()).front_span(color);
+        square.intersects(self.get_pieces(Kind::Pawn, color))
+            && !span.intersects(self.get_pieces(Kind::Pawn, !color))
+    }
+
+    // Another pawn of the same color shares its file
+    pub fn is_doubled_pawn(&self, square: Bitboard, color: Color) -> bool {
+        let pawns = self.get_pieces(Kind::Pawn, color);
+        square.intersects(pawns) && square.file_mask().intersects(pawns & !square)
+    }
+
+    // No pawn of the same color on an adjacent file
+    pub fn is_isolated_pawn(&self, square: Bitboard, color: Color) -> bool {
+        let pawns = self.get_pieces(Kind::Pawn, color);
+        square.intersects(pawns) && !square.adjacent_files_mask().intersects(pawns)
+    }
+
+    pub fn knight_outpost(&self, square: Bitboard, color: Color) -> bool {
+        square.intersects(self.get_pieces(Kind::Knight, color)) && self.is_outpost(square, color)
+    }
+
+    // At least one bishop on each square color
+    pub fn has_bishop_pair(&self, color: Color) -> bool {
+        let bishops = self.get_pieces(Kind::Bishop, color);
+        bishops.intersects(Bitboard::LIGHT_SQUARES) && bishops.intersects(Bitboard::DARK_SQUARES)
+    }
+
+    // Squares the bishops can move to, added up over every bishop
+    pub fn bishop_mobility(&self, color: Color) -> u32 {
+        let own = self.get_color_mask(color);
+        self.get_pieces(Kind::Bishop, color)
+            .map(|bishop| (Self::bishop_attacks_hq(bishop, self.anything()) & !own).count() as u32)
+            .sum()
+    }
+
+    // Two rooks are connected when they see each other along a rank or file
+    pub fn connected_rooks(&self, color: Color) -> bool {
+        let rooks = self.get_pieces(Kind::Rook, color);
+        let occupied = self.anything();
+        for rook in rooks {
+            for direction in Direction::STRAIGHT_MOVES {
+                let mut square = rook.shift(direction);
+                while !square.is_empty() && !square.intersects(occupied) {
+                    square = square.shift(direction);
+                }
+                if square.intersects(rooks) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    pub fn rook_on_seventh(&self, color: Color) -> bool {
+        let seventh_rank = match color {
+            Color::White => Bitboard::RANK_7,
+            Color::Black => Bitboard::RANK_2,
+        };
+        self.get_pieces(Kind::Rook, color).intersects(seventh_rank)
+    }
+
+    // `color`'s king is stuck on its back rank, every square in front of it taken by its own
+    // pieces or covered by the opponent, and an enemy rook or queen can land on that rank where
+    // nothing but the king defends it, with nothing in between to block the check
+    pub fn detect_back_rank_mate_threat(&self, color: Color) -> bool {
+        let (back_rank, next_rank) = match color {
+            Color::White => (Bitboard::RANK_1, Bitboard::RANK_2),
+            Color::Black => (Bitboard::RANK_8, Bitboard::RANK_7),
+        };
+        let Ok(king_idx) = self.king_position(color) else {
+            return false;
+        };
+        if !back_rank.intersects(Bitboard(1 << king_idx)) {
+            return false;
+        }
+        let own = self.get_color_mask(color);
+        let luft = self.king_attacks_lookup[king_idx] & next_rank;
+        if !(luft & !own & !self.generate_king_danger_squares(color)).is_empty() {
+            return false;
+        }
+
+        let enemy = self.get_color_mask(!color);
+        let occupancy = self.anything();
+        let mut landings = Bitboard(0);
+        for slider in (self.rooks | self.queens) & enemy {
+            landings |= Self::rook_attacks_hq(slider, occupancy) & back_rank & !enemy;
+        }
+        let king = Bitboard(1 << king_idx);
+        landings.any(|square| {
+            (self.attacks_to(square) & own & !self.kings).is_empty()
+                && !Bitboard::between(square, king).intersects(occupancy)
+        })
+    }
+
+    // Squares `color`'s king starts on, crosses or lands on when castling to either side
+    pub fn generate_castling_check_mask(&self, color: Color) -> Bitboard {
+        // c1 to g1
+        let white = Bitboard(0b0111_1100);
+        match color {
+            Color::White => white,
+            Color::Black => white.flip_vertical(),
+        }
+    }
+
+    pub fn can_castle_kingside(&self, color: Color) -> bool {
+        self.castling.get_castling_right(match color {
+            Color::White => CastlingRights::WHITE_KINGSIDE,
+            Color::Black => CastlingRights::BLACK_KINGSIDE,
+        })
+    }
+
+    pub fn can_castle_queenside(&self, color: Color) -> bool {
+        self.castling.get_castling_right(match color {
+            Color::White => CastlingRights::WHITE_QUEENSIDE,
+            Color::Black => CastlingRights::BLACK_QUEENSIDE,
+        })
+    }
+
+    pub fn has_any_castling_rights(&self, color: Color) -> bool {
+        self.can_castle_kingside(color) || self.can_castle_queenside(color)
+    }
+
+    // Every piece on the board, White's first, then by kind from pawns to the king, then by square
+    pub fn piece_list(&self) -> Vec<Piece> {
+        let mut pieces = self
+            .anything()
+            .filter_map(|square| self.get_piece(square))
+            .collect::<Vec<Piece>>();
+        pieces.sort_by_key(|piece| (piece.color, piece.kind, piece.position.idx()));
+        pieces
+    }
+
+    pub fn piece_list_for(&self, color: Color) -> Vec<Piece> {
+        let mut pieces = self
+            .get_color_mask(color)
+            .filter_map(|square| self.get_piece(square))
+            .collect::<Vec<Piece>>();
+        pieces.sort_by_key(|piece| (piece.kind, piece.position.idx()));
+        pieces
+    }
+
+    // Every piece on the board, kings included
+    pub fn piece_count(&self) -> u32 {
+        self.anything().count() as u32
+    }
+
+    // Knights, bishops, rooks and queens of both colors. Kings are always there, so they're left
+    // out
+    pub fn non_pawn_count(&self) -> u32 {
+        (self.knights | self.bishops | self.rooks | self.queens).count() as u32
+    }
+
+    pub fn non_pawn_count_for(&self, color: Color) -> u32 {
+        ((self.knights | self.bishops | self.rooks | self.queens) & self.get_color_mask(color))
+            .count() as u32
+    }
+
+    // Dead positions under FIDE rules: KK, KNK, KBK and any number of bishops all on the same
+    // square color
+    pub fn is_draw_by_insufficient_material(&self) -> bool {
+        self.is_insufficient_material(false)
+    }
+
+    // `knnk_is_draw` also treats king and two knights against a bare king as a draw. Mate is
+    // possible there, but it can't be forced
+    pub fn is_insufficient_material(&self, knnk_is_draw: bool) -> bool {
+        // Pawns can always promote
+        if !(self.pawns | self.rooks | self.queens).is_empty() {
+            return false;
+        }
+
+        let minor_pieces = self.knights | self.bishops;
+        if minor_pieces.count() <= 1 {
+            return true;
+        }
+
+        if self.knights.is_empty()
+            && (!self.bishops.intersects(Bitboard::LIGHT_SQUARES)
+                || !self.bishops.intersects(Bitboard::DARK_SQUARES))
+        {
+            return true;
+        }
+
+        knnk_is_draw
+            && self.bishops.is_empty()
+            && self.knights.count() == 2
+            && (self.knights == self.knights & self.white
+                || self.knights == self.knights & self.black)
+    }
+
+    // Whether `color` can't mate whatever the opponent does: a bare king, or a lone knight or
+    // bishop against a bare king. Anything else is assumed to be able to mate, even where only
+    // a helpmate exists
+    pub fn is_insufficient_material_for_color(&self, color: Color) -> bool {
+        let own = self.get_color_mask(color);
+        if (self.pawns | self.rooks | self.queens).intersects(own) {
+            return false;
+        }
+        let minor_pieces = (self.knights | self.bishops) & own;
+        match minor_pieces.count() {
+            0 => true,
+            1 => (self.get_color_mask(!color) & !self.kings).is_empty(),
+            _ => false,
+        }
+    }
+
+    pub fn flip_turn(&mut self) {
+        self.turn = !self.turn;
+        self.zobrist_hash ^= ZOBRIST.side_to_move(Color::Black);
+    }
+
+    pub const fn hash(&self) -> u64 {
+        self.zobrist_hash
+    }
+
+    // The hash as it should be, from every piece and state field. `zobrist_hash` must always
+    // match it
+    pub fn zobrist_hash_from_scratch(&self) -> u64 {
+        let pieces = self
+            .piece_list()
+            .into_iter()
+            .fold(0, |hash, piece| hash ^ ZOBRIST.piece(piece));
+        pieces
+            ^ ZOBRIST.side_to_move(self.turn)
+            ^ ZOBRIST.castling(self.castling)
+            ^ ZOBRIST.en_passant(self.en_passant)
+    }
+
+    // For after the state fields were assigned directly
+    pub fn refresh_hash(&mut self) {
+        self.zobrist_hash = self.zobrist_hash_from_scratch();
+    }
+
+    pub fn set_en_passant(&mut self, en_passant: Option<Bitboard>) {
+        self.zobrist_hash ^= ZOBRIST.en_passant(self.en_passant) ^ ZOBRIST.en_passant(en_passant);
+        self.en_passant = en_passant;
+    }
+
+    pub fn set_castling(&mut self, castling: CastlingRights) {
+        self.zobrist_hash ^= ZOBRIST.castling(self.castling) ^ ZOBRIST.castling(castling);
+        self.castling = castling;
+    }
+
+    // Panics in debug builds when `mov` doesn't fit the position: nothing or the wrong piece on
+    // the origin, not the side to move, or a capture that doesn't match the board
+    pub fn assert_legal_move(&self, mov: Move) {
+        if cfg!(debug_assertions) {
+            let mut problems = vec![];
+            match self.get_piece(mov.from) {
+                None => problems.push("No piece on the origin square".to_string()),
+                Some(piece) if piece.color != mov.what.color || piece.kind != mov.what.kind => {
+                    problems.push(format!(
+                        "The move is for a {}, but {piece} is there",
+                        mov.what
+                    ));
+                }
+                Some(_) => {}
+            }
+            if mov.what.color != self.turn {
+                problems.push(format!("{} moved on {}'s turn", mov.what.color, self.turn));
+            }
+            if mov.to.intersects(self.get_color_mask(mov.what.color)) {
+                problems.push("The destination holds a friendly piece".to_string());
+            }
+            match mov.capture {
+                Some(capture) if self.get_piece(capture.position) != Some(capture) => {
+                    problems.push(format!(
+                        "Captures {capture}, but {} is there",
+                        self.get_piece(capture.position)
+                            .map_or("nothing".to_string(), |piece| piece.to_string())
+                    ));
+                }
+                None if self.anything().intersects(mov.to) => {
+                    problems.push("The destination is occupied but it's not a capture".to_string());
+                }
+                _ => {}
+            }
+            assert!(
+                problems.is_empty(),
+                "Illegal move {mov}:\n{}\n{self}",
+                problems.join("\n")
+            );
+        }
+    }
+
+    pub fn move_piece(&mut self, mov: Move) {
+        self.assert_legal_move(mov);
+        let piece = mov.what;
+        // Only a double pawn push leaves an en passant target behind. Any other move, en passant
+        // captures included, clears the previous one
+        self.set_en_passant(mov.en_passant_target());
+
+        if let Some((rook_origin, rook_destination)) = mov.castle_move {
+            self.update_piece_position(
+                piece.with_kind(Kind::Rook).on_square(rook_origin),
+                rook_destination,
+            );
+        }
+
+        // King moves carry their lost rights. Moving a rook, or capturing one, on its starting
+        // square loses that side. Rights are never given back here: `Game::unmake_move` restores
+        // them from the history
+        let lost_rights = mov.castling_rights_change
+            | CastlingRights::rights_for_rook_square(mov.from)
+            | CastlingRights::rights_for_rook_square(mov.to);
+        let mut castling = self.castling;
+        castling.set_castling_right(lost_rights, false);
+        self.set_castling(castling);
+
+        // We handle capture first, so we don't face issues when trying to eat a piece of the same
+        // type
+        if let Some(capture) = mov.capture {
+            self.clear_piece(capture);
+        }
+
+        // `what` keeps the original square when unmaking, so take the origin from the move
+        self.update_piece_position(piece.on_square(mov.from), mov.to);
+        if let Some(promotion) = mov.promotion {
+            self.pawns.clear_bit(mov.to);
+            self.piece_bitboard_mut(promotion).set_bit(mov.to);
+            self.zobrist_hash ^= ZOBRIST.piece(piece.on_square(mov.to))
+                ^ ZOBRIST.piece(piece.with_kind(promotion).on_square(mov.to));
+        }
+
+        // self.attacked_squares = self.calculate_attacked_squares();
+
+        #[cfg(debug_assertions)]
+        {
+            self.assert_sync();
+        }
+    }
+
+    // Everything that makes a position impossible to reach in a legal game, as human readable
+    // descriptions
+    pub fn assert_legal_position(&self) -> std::result::Result<(), Vec<String>> {
+        let mut violations = vec![];
+
+        for color in [Color::White, Color::Black] {
+            let kings = self.get_pieces(Kind::King, color).count();
+            if kings != 1 {
+                violations.push(format!("{color} has {kings} kings"));
+            }
+        }
+
+        let pieces = [
+            self.pawns,
+            self.knights,
+            self.bishops,
+            self.rooks,
+            self.queens,
+            self.kings,
+        ];
+        let all_pieces = pieces.iter().fold(Bitboard(0), |all, &kind| all | kind);
+        if pieces.iter().map(Bitboard::count).sum::<usize>() != all_pieces.count() {
+            violations.push("Some squares hold more than one kind of piece".to_string());
+        }
+        if all_pieces != self.anything() || self.white.intersects(self.black) {
+            violations.push("Piece bitboards are out of sync with the color masks".to_string());
+        }
+
+        if self.pawns.intersects(Bitboard::PAWN_PROMOTION_MASK) {
+            violations.push(format!(
+                "Pawns on the first or last rank:{}",
+                self.pawns & Bitboard::PAWN_PROMOTION_MASK
+            ));
+        }
+
+        violations.append(&mut self.state_violations());
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    // The part of `assert_legal_position` the moves depend on: the side to move can take the
+    // enemy king, or en passant and castling rights point at pawns, kings or rooks that aren't
+    // there
+    pub fn state_violations(&self) -> Vec<String> {
+        let mut violations = vec![];
+
+        // The side that just moved can't have left its king in check
+        if let Ok(true) = self.is_check(!self.turn) {
+            violations.push(format!(
+                "{} is in check on {}'s turn",
+                !self.turn, self.turn
+            ));
+        }
+
+        if let Some(en_passant) = self.en_passant {
+            // The pawn that just pushed two squares, and the square it came from
+            let (pushed_pawn, origin, rank) = match self.turn {
+                Color::White => (en_passant.south(), en_passant.north(), 5),
+                Color::Black => (en_passant.north(), en_passant.south(), 2),
+            };
+            if en_passant.rank_mask() != Bitboard::from_square(0, rank).rank_mask()
+                || self.anything().intersects(en_passant | origin)
+                || !pushed_pawn.intersects(self.get_pieces(Kind::Pawn, !self.turn))
+            {
+                violations.push(format!(
+                    "En passant square {} doesn't follow a double pawn push",
+                    en_passant.to_algebraic().unwrap_or_default()
+                ));
+            }
+        }
+
+        for (right, color, rook_square, name) in [
+            (
+                CastlingRights::WHITE_KINGSIDE,
+                Color::White,
+                7,
+                "White kingside",
+            ),
+            (
+                CastlingRights::WHITE_QUEENSIDE,
+                Color::White,
+                0,
+                "White queenside",
+            ),
+            (
+                CastlingRights::BLACK_KINGSIDE,
+                Color::Black,
+                63,
+                "Black kingside",
+            ),
+            (
+                CastlingRights::BLACK_QUEENSIDE,
+                Color::Black,
+                56,
+                "Black queenside",
+            ),
+        ] {
+            if !self.castling.get_castling_right(right) {
+                continue;
+            }
+            let king_square = Bitboard::KING_INITIAL & self.get_color_mask(color);
+            if !king_square.intersects(self.kings)
+                || !Bitboard(1 << rook_square).intersects(self.get_pieces(Kind::Rook, color))
+            {
+                violations.push(format!(
+                    "{name} castling is allowed but the king or rook has moved"
+                ));
+            }
+        }
+
+        violations
+    }
+
+    pub fn assert_sync(&self) {
+        // verify that color masks are correct
+        assert_eq!(
+            self.white & self.black,
+            Bitboard(0),
+            "White and black overlap {} {}",
+            self.white,
+            self.black
+        );
+        // TODO: check that inter-piece masks dont collide, and always intersect with color_masks
+        assert_eq!(
+            self.zobrist_hash,
+            self.zobrist_hash_from_scratch(),
+            "Zobrist hash out of sync\n{self}"
+        );
+    }
+
+    // Only puts the pieces back. En passant and castling rights are restored by the caller
+    pub fn unmove_piece(&mut self, mov: Move) {
+        if let Some(promotion) = mov.promotion {
+            self.piece_bitboard_mut(promotion).clear_bit(mov.to);
+            self.pawns.set_bit(mov.to);
+            self.zobrist_hash ^= ZOBRIST.piece(mov.what.with_kind(promotion).on_square(mov.to))
+                ^ ZOBRIST.piece(mov.what.on_square(mov.to));
+        }
+        self.update_piece_position(mov.what.on_square(mov.to), mov.from);
+        // restore old piece
+        if let Some(captured_piece) = mov.capture {
+            self.spawn_piece(captured_piece);
+        }
+
+        if let Some((rook_origin, rook_destination)) = mov.castle_move {
+            self.update_piece_position(
+                mov.what.with_kind(Kind::Rook).on_square(rook_destination),
+                rook_origin,
+            );
+        }
+    }
+
+    // `unmove_piece`, but checks first that the board is in the state `mov` left it in. Nothing
+    // is touched on error
+    pub fn try_unmove_piece(&mut self, mov: Move) -> std::result::Result<(), BoardError> {
+        let reverse = mov.reverse();
+        let found = self.get_piece(reverse.from);
+        if found != Some(reverse.what) {
+            return Err(BoardError::PieceMismatch {
+                expected: reverse.what,
+                found,
+            });
+        }
+        if self.is_square_occupied(reverse.to) {
+            return Err(BoardError::SquareOccupied(reverse.to));
+        }
+        // En passant captures are put back away from `to`
+        if let Some(capture) = mov.capture {
+            if capture.position != mov.to && self.is_square_occupied(capture.position) {
+                return Err(BoardError::SquareOccupied(capture.position));
+            }
+        }
+        if let Some((rook_origin, rook_destination)) = mov.castle_move {
+            let rook = mov.what.with_kind(Kind::Rook).on_square(rook_destination);
+            let found = self.get_piece(rook_destination);
+            if found != Some(rook) {
+                return Err(BoardError::PieceMismatch {
+                    expected: rook,
+                    found,
+                });
+            }
+            if self.is_square_occupied(rook_origin) {
+                return Err(BoardError::SquareOccupied(rook_origin));
+            }
+        }
+        self.unmove_piece(mov);
+        Ok(())
+    }
+
+    // Moves `piece` from its `position` to `to`, keeping the color mask, the piece bitboard and
+    // the king position in sync. `to` must be empty: clear any captured piece first
+    pub fn update_piece_position(&mut self, piece: Piece, to: Bitboard) {
+        #[cfg(debug_assertions)]
+        {
+            assert!(
+                !self.anything().intersects(to),
+                "Tried to move {} to a busy square\n{}{}",
+                piece,
+                self,
+                to
+            );
+        }
+        let from = piece.position;
+        let color_mask = match piece.color {
+            Color::White => &mut self.white,
+            Color::Black => &mut self.black,
+        };
+        color_mask.move_bit(from, to);
+        self.piece_bitboard_mut(piece.kind).move_bit(from, to);
+        self.zobrist_hash ^= ZOBRIST.piece(piece) ^ ZOBRIST.piece(piece.on_square(to));
+        if piece.kind == Kind::King {
+            match piece.color {
+                Color::White => self.king_position.white = Some(to.idx()),
+                Color::Black => self.king_position.black = Some(to.idx()),
+            }
+        }
+    }
+
+    pub fn spawn_piece(&mut self, piece: Piece) {
+        let color_mask = match piece.color {
+            Color::White => &mut self.white,
+            Color::Black => &mut self.black,
+        };
+
+        let position = piece.position;
+
+        #[cfg(debug_assertions)]
+        {
+            assert!(
+                !color_mask.intersects(piece.position),
+                "Tried to spawn a piece on a busy square\n {} {} {}",
+                self,
+                piece.position,
+                piece
+            );
+        }
+
+        color_mask.set_bit(position);
+        self.piece_bitboard_mut(piece.kind).set_bit(position);
+        self.zobrist_hash ^= ZOBRIST.piece(piece);
+        if piece.kind == Kind::King {
+            match piece.color {
+                Color::White => self.king_position.white = Some(position.idx()),
+                Color::Black => self.king_position.black = Some(position.idx()),
+            }
+            #[cfg(debug_assertions)]
+            {
+                let color_mask = self.get_color_mask(piece.color);
+                assert!(
+                    (self.kings & color_mask).count() == 1,
+                    "{} {} {}",
+                    self.kings,
+                    color_mask,
+                    (self.kings & color_mask).count()
+                );
+                assert!(
+                    self.king_position.get(piece.color).unwrap() == position.idx(),
+                    "King position out of sync with king"
+                );
+            }
+        }
+    }
+
+    pub fn get_color_mask(&self, color: Color) -> Bitboard {
+        match color {
+            Color::White => self.white,
+            Color::Black => self.black,
+        }
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn colorize(letter: char) -> String {
+    // if check
+    let answer: String = if letter.is_ascii_uppercase() {
+        format!("\x1b[0;31m{}\x1b[0m", letter)
+    } else {
+        format!("\x1b[0;34m{}\x1b[0m", letter)
+    };
+    answer
+}
+
+impl Display for Board {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        let mut board = String::new();
+        for rank in (0..8).rev() {
+            for file in 0..8 {
+                let square = Bitboard::from_square(file, rank);
+                let piece = self.get_piece(square);
+                match piece {
+                    Some(piece) => {
+                        board += &format!("{} ", colorize(to_letter(Some(piece))));
                     }
                     None => {
                         if square & self.attacked_squares != Bitboard(0) {
@@ -560,3 +1728,783 @@ impl Display for Board {
         write!(f, "{board}")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::evaluate, Game};
+
+    fn square(algebraic: &str) -> Bitboard {
+        Bitboard::from_algebraic(algebraic).unwrap()
+    }
+
+    #[test]
+    fn outpost_detection() {
+        // No black pawns on the d, e or f files, e5 supported by d4
+        let game = Game::new("4k3/pp4pp/8/4N3/3P4/8/8/4K3 w - - 0 1").unwrap();
+        assert!(game.board.is_outpost(square("e5"), Color::White));
+        assert!(game.board.knight_outpost(square("e5"), Color::White));
+
+        // f7 can kick the knight
+        let game = Game::new("4k3/pp3ppp/8/4N3/3P4/8/8/4K3 w - - 0 1").unwrap();
+        assert!(!game.board.is_outpost(square("e5"), Color::White));
+
+        // Not supported by a pawn
+        let game = Game::new("4k3/pp4pp/8/4N3/8/3P4/8/4K3 w - - 0 1").unwrap();
+        assert!(!game.board.is_outpost(square("e5"), Color::White));
+    }
+
+    #[test]
+    fn bishop_attacks() {
+        let mut board = Board::new();
+        board.spawn_piece(Piece::new(Color::Black, Kind::Bishop, square("e4")));
+        let expected = [
+            "d3", "c2", "b1", "f5", "g6", "h7", "d5", "c6", "b7", "a8", "f3", "g2", "h1",
+        ]
+        .into_iter()
+        .fold(Bitboard(0), |attacks, algebraic| {
+            attacks | square(algebraic)
+        });
+        assert_eq!(board.generate_bishop_attacks(Color::White), expected);
+
+        // Blocked on c6, which is still attacked
+        board.spawn_piece(Piece::new(Color::White, Kind::Pawn, square("c6")));
+        let blocked = expected & !(square("b7") | square("a8"));
+        assert_eq!(board.generate_bishop_attacks(Color::White), blocked);
+    }
+
+    #[test]
+    fn slider_attacks() {
+        let mut board = Board::new();
+        board.spawn_piece(Piece::new(Color::Black, Kind::Rook, square("a1")));
+        board.spawn_piece(Piece::new(Color::Black, Kind::Queen, square("h8")));
+        // The rook stops at the white knight, and the queen's diagonal at the rook
+        board.spawn_piece(Piece::new(Color::White, Kind::Knight, square("a4")));
+
+        let rook = Board::ray_attacks(square("a1"), &Direction::STRAIGHT_MOVES, board.anything());
+        let queen = Board::ray_attacks(square("h8"), &Direction::SLIDING_MOVES, board.anything());
+        assert!(rook.intersects(square("a4")) && !rook.intersects(square("a5")));
+        assert!(queen.intersects(square("a1")));
+        assert_eq!(board.generate_slider_attacks(Color::White), rook | queen);
+        assert_eq!(board.generate_slider_attacks(Color::Black), Bitboard(0));
+    }
+
+    #[test]
+    fn pawn_structure() {
+        // White: passed a5, doubled and isolated c-pawns, e4 blocked by e5 and backed up by f2
+        // Black: e5 blocked by e4, h7 isolated
+        let game = Game::new("4k3/7p/8/P3p3/2P1P3/2P5/5P2/4K3 w - - 0 1").unwrap();
+        let board = game.board;
+
+        assert!(board.is_passed_pawn(square("a5"), Color::White));
+        assert!(!board.is_passed_pawn(square("e4"), Color::White));
+        assert!(board.is_passed_pawn(square("c4"), Color::White));
+        assert!(!board.is_passed_pawn(square("e5"), Color::Black));
+        assert!(!board.is_passed_pawn(square("a4"), Color::White));
+
+        assert!(board.is_doubled_pawn(square("c3"), Color::White));
+        assert!(board.is_doubled_pawn(square("c4"), Color::White));
+        assert!(!board.is_doubled_pawn(square("e4"), Color::White));
+
+        assert!(board.is_isolated_pawn(square("c3"), Color::White));
+        assert!(board.is_isolated_pawn(square("a5"), Color::White));
+        assert!(board.is_isolated_pawn(square("h7"), Color::Black));
+        assert!(!board.is_isolated_pawn(square("e4"), Color::White));
+    }
+
+    #[test]
+    fn swap_colors() {
+        let mut black_to_move = Board::starting_position();
+        black_to_move.flip_turn();
+        assert_eq!(Board::starting_position().swap_colors(), black_to_move);
+
+        let game = Game::new("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w Kq - 0 1")
+            .unwrap();
+        let swapped = game.board.swap_colors();
+        assert_eq!(
+            Game::new("r3k2r/pppbbppp/2n2q1P/1P2p3/3pn3/BN2PNP1/P1PPQPB1/R3K2R b Qk - 0 1")
+                .unwrap()
+                .board,
+            swapped
+        );
+        assert_eq!(swapped.swap_colors(), game.board);
+        assert_eq!(evaluate(&game.board), evaluate(&swapped));
+    }
+
+    #[test]
+    fn starting_position() {
+        let game = Game::new(Game::STARTING_FEN).unwrap();
+        assert_eq!(Board::starting_position(), game.board);
+    }
+
+    #[test]
+    fn attacks_to() {
+        let game = Game::new("4k3/8/2n5/3p4/4P3/5K2/8/B3R3 w - - 0 1").unwrap();
+        // The d5 pawn, the f3 king and the e1 rook see e4, the knight on c6 doesn't
+        assert_eq!(
+            game.board.attacks_to(square("e4")),
+            square("d5") | square("f3") | square("e1")
+        );
+        // Both colors count
+        assert_eq!(
+            game.board.attacks_to(square("d4")),
+            square("c6") | square("a1")
+        );
+    }
+
+    #[test]
+    fn bishop_pair() {
+        let game = Game::new(Game::STARTING_FEN).unwrap();
+        assert!(game.board.has_bishop_pair(Color::White));
+        assert_eq!(game.board.bishop_mobility(Color::White), 0);
+
+        let game = Game::new("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1").unwrap();
+        assert!(game.board.has_bishop_pair(Color::White));
+        assert_eq!(game.board.bishop_mobility(Color::White), 7 + 7);
+
+        // Both bishops on dark squares don't make a pair
+        let game = Game::new("4k3/8/8/8/8/8/3B4/2B1K3 w - - 0 1").unwrap();
+        assert!(!game.board.has_bishop_pair(Color::White));
+        assert!(!game.board.has_bishop_pair(Color::Black));
+    }
+
+    #[test]
+    fn piece_list() {
+        let game = Game::new("4k3/8/8/8/8/8/1P6/R3K2R b KQ - 0 1").unwrap();
+        let pieces = game
+            .board
+            .piece_list()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<String>>();
+        assert_eq!(
+            pieces,
+            game.board
+                .piece_list_for(Color::White)
+                .iter()
+                .chain(&game.board.piece_list_for(Color::Black))
+                .map(ToString::to_string)
+                .collect::<Vec<String>>()
+        );
+        let kinds = game
+            .board
+            .piece_list()
+            .iter()
+            .map(|piece| (piece.color, piece.kind, piece.position))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                (Color::White, Kind::Pawn, square("b2")),
+                (Color::White, Kind::Rook, square("a1")),
+                (Color::White, Kind::Rook, square("h1")),
+                (Color::White, Kind::King, square("e1")),
+                (Color::Black, Kind::King, square("e8")),
+            ]
+        );
+    }
+
+    #[test]
+    fn in_check() {
+        assert!(!Board::starting_position().in_check());
+        let board = Game::new("4k3/8/8/8/8/8/8/4RK2 b - - 0 1").unwrap().board;
+        assert!(board.in_check());
+        assert_eq!(board.is_check(Color::White), Ok(false));
+    }
+
+    #[test]
+    fn between_and_ray() {
+        let squares = |list: &str| {
+            list.split_whitespace()
+                .fold(Bitboard(0), |squares, algebraic| {
+                    squares | square(algebraic)
+                })
+        };
+        let file = squares("d1 d2 d3 d4 d5 d6 d7 d8");
+        let rank = squares("a4 b4 c4 d4 e4 f4 g4 h4");
+        let diagonal = squares("a1 b2 c3 d4 e5 f6 g7 h8");
+        let antidiagonal = squares("g1 f2 e3 d4 c5 b6 a7");
+        // From d4 in each of the eight directions
+        for (to, between, ray) in [
+            ("d7", "d5 d6", file),
+            ("d1", "d2 d3", file),
+            ("h4", "e4 f4 g4", rank),
+            ("a4", "b4 c4", rank),
+            ("g7", "e5 f6", diagonal),
+            ("b2", "c3", diagonal),
+            ("a7", "c5 b6", antidiagonal),
+            ("g1", "e3 f2", antidiagonal),
+        ] {
+            assert_eq!(
+                Bitboard::between(square("d4"), square(to)),
+                squares(between),
+                "d4 {to}"
+            );
+            assert_eq!(Bitboard::ray(square("d4"), square(to)), ray, "d4 {to}");
+            // Either way round
+            assert_eq!(Bitboard::ray(square(to), square("d4")), ray, "{to} d4");
+        }
+        // Neighbours have nothing between them, but still a ray
+        assert_eq!(Bitboard::between(square("d4"), square("d5")), Bitboard(0));
+        assert_eq!(Bitboard::ray(square("d4"), square("d5")), file);
+        // Off any shared line, or the same square
+        for (a, b) in [("d4", "e6"), ("a1", "h7"), ("d4", "d4")] {
+            assert_eq!(
+                Bitboard::between(square(a), square(b)),
+                Bitboard(0),
+                "{a} {b}"
+            );
+            assert_eq!(Bitboard::ray(square(a), square(b)), Bitboard(0), "{a} {b}");
+        }
+    }
+
+    #[test]
+    fn square_lists() {
+        let squares = square("h8") | square("a1") | square("e4");
+        assert_eq!(squares.to_square_list(), [0, 28, 63]);
+        assert_eq!(squares.to_algebraic_list(), ["a1", "e4", "h8"]);
+        let white = Board::starting_position().white.to_square_set();
+        assert_eq!(
+            squares
+                .to_square_set()
+                .intersection(&white)
+                .collect::<Vec<&u8>>(),
+            [&0]
+        );
+        assert!(Bitboard(0).to_square_list().is_empty());
+    }
+
+    #[test]
+    fn square_occupancy() {
+        let board = Board::starting_position();
+        assert_eq!(board.color_of_square(square("e2")), Some(Color::White));
+        assert_eq!(board.color_of_square(square("d8")), Some(Color::Black));
+        assert_eq!(board.color_of_square(square("e4")), None);
+        assert!(board.is_square_occupied(square("a1")));
+        assert!(!board.is_square_occupied(square("a3")));
+        assert!(board.white[square("e2").idx()]);
+        assert!(!board.white[square("e4").idx()]);
+    }
+
+    #[test]
+    fn promotions() {
+        let mut game = Game::new("1r2k3/2P5/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let before = game.board;
+        for lan in ["c7c8n", "c7b8q"] {
+            let mov = game.parse_move(lan).unwrap();
+            game.make_move(mov);
+            let promoted = game.board.get_piece(mov.to).unwrap();
+            assert_eq!(promoted.kind, mov.promotion.unwrap());
+            assert_eq!(promoted.color, Color::White);
+            assert!(!game.board.pawns.intersects(mov.to));
+            game.unmake_move(mov);
+            assert_eq!(game.board, before);
+        }
+    }
+
+    #[test]
+    fn pin_rays() {
+        // The knight on e4 is pinned by the rook, the bishop on c3 by the bishop on a5. The pawn
+        // on g3 stands between the king and a knight, which can't pin
+        let board = Game::new("4k3/8/4r3/b7/4N3/2B3n1/6P1/4K3 w - - 0 1")
+            .unwrap()
+            .board;
+        let pins = board
+            .pin_rays(Color::White)
+            .into_iter()
+            .filter(|(pinned, _)| !pinned.is_empty())
+            .collect::<Vec<(Bitboard, Bitboard)>>();
+        assert_eq!(
+            pins,
+            [
+                (
+                    square("e4"),
+                    square("e2") | square("e3") | square("e4") | square("e5") | square("e6")
+                ),
+                (
+                    square("c3"),
+                    square("d2") | square("c3") | square("b4") | square("a5")
+                ),
+            ]
+        );
+        assert!(Board::starting_position()
+            .pin_rays(Color::White)
+            .iter()
+            .all(|(pinned, allowed)| pinned.is_empty() && allowed.is_empty()));
+    }
+
+    #[test]
+    fn pinned_pieces_and_pin_ray() {
+        let board = Game::new("4k3/8/4r3/b7/4N3/2B3n1/6P1/4K3 w - - 0 1")
+            .unwrap()
+            .board;
+        assert_eq!(
+            board.pinned_pieces(Color::White),
+            square("e4") | square("c3")
+        );
+        assert!(board.pinned_pieces(Color::Black).is_empty());
+        assert_eq!(
+            board.pin_ray(square("c3").idx()),
+            square("d2") | square("c3") | square("b4") | square("a5")
+        );
+        // Unpinned pieces and empty squares aren't held back
+        assert_eq!(board.pin_ray(square("g2").idx()), Bitboard::MAX);
+        assert_eq!(board.pin_ray(square("d4").idx()), Bitboard::MAX);
+
+        // The knight can't stay on the file, the bishop only slides along its diagonal
+        assert!(board.gen_moves_from_piece(square("e4")).is_empty());
+        let mut bishop_moves = board
+            .gen_moves_from_piece(square("c3"))
+            .iter()
+            .map(Move::lan_string)
+            .collect::<Vec<String>>();
+        bishop_moves.sort();
+        assert_eq!(bishop_moves, ["c3a5", "c3b4", "c3d2"]);
+    }
+
+    #[test]
+    fn is_legal_move() {
+        let mut game = Game::new("4k3/8/4r3/8/4N3/8/8/4K3 w - - 0 1").unwrap();
+        let king_move = game.parse_move("e1d1").unwrap();
+        assert!(game.board.is_legal_move(king_move));
+        // The pinned knight can't move, and a move from another position doesn't fit this one
+        let knight_move = Move::new(
+            square("e4"),
+            square("c5"),
+            Piece::new(Color::White, Kind::Knight, square("e4")),
+        );
+        assert!(!game.board.is_legal_move(knight_move));
+        game.make_move(king_move);
+        assert!(!game.board.is_legal_move(king_move));
+    }
+
+    #[test]
+    fn threatened_and_hanging_pieces() {
+        // The rook on d5 is attacked but defended by the pawn. The knight on b4 and the pawn on
+        // e6 are attacked and undefended, and so is the bishop on d2 on White's side
+        let board = Game::new("6k1/8/4p3/3r4/1n3N2/8/3B4/6K1 w - - 0 1")
+            .unwrap()
+            .board;
+        assert_eq!(
+            board.threatened_pieces(Color::Black),
+            square("d5") | square("b4") | square("e6")
+        );
+        assert_eq!(board.threatened_pieces(Color::White), square("d2"));
+        assert!(board.is_piece_hanging(square("b4"), Color::Black));
+        assert!(board.is_piece_hanging(square("e6"), Color::Black));
+        assert!(board.is_piece_hanging(square("d2"), Color::White));
+        assert!(!board.is_piece_hanging(square("d5"), Color::Black));
+        // Defended but not attacked
+        assert!(!board.is_piece_hanging(square("f4"), Color::White));
+
+        let board = Board::starting_position();
+        for color in [Color::White, Color::Black] {
+            assert!(board.threatened_pieces(color).is_empty());
+            assert!(board
+                .get_color_mask(color)
+                .all(|square| !board.is_piece_hanging(square, color)));
+        }
+    }
+
+    #[test]
+    fn castling_check_mask() {
+        let board = Board::starting_position();
+        assert_eq!(
+            board
+                .generate_castling_check_mask(Color::White)
+                .to_algebraic_list(),
+            ["c1", "d1", "e1", "f1", "g1"]
+        );
+        assert_eq!(
+            board
+                .generate_castling_check_mask(Color::Black)
+                .to_algebraic_list(),
+            ["c8", "d8", "e8", "f8", "g8"]
+        );
+    }
+
+    #[test]
+    fn back_rank_mate_threat() {
+        let threat = |fen: &str, color: Color| {
+            Game::new(fen)
+                .unwrap()
+                .board
+                .detect_back_rank_mate_threat(color)
+        };
+        // Ra8 would be mate
+        assert!(threat("6k1/5ppp/8/8/8/8/5PPP/R5K1 b - - 0 1", Color::Black));
+        // Unless the king has some air, or the rook on d8 guards the rank
+        assert!(!threat(
+            "6k1/5pp1/7p/8/8/8/5PPP/R5K1 b - - 0 1",
+            Color::Black
+        ));
+        assert!(!threat(
+            "3r2k1/5ppp/8/8/8/8/5PPP/R5K1 b - - 0 1",
+            Color::Black
+        ));
+        // Ra8 is no check with the knight in the way
+        assert!(!threat("4n1k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", Color::Black));
+        // White's king is just as boxed in, but nothing can reach its back rank
+        assert!(!threat(
+            "6k1/5ppp/8/8/8/8/5PPP/R5K1 b - - 0 1",
+            Color::White
+        ));
+        // Lucena and Philidor: the defending king has room on the second rank
+        assert!(!threat("1K1k4/1P6/8/8/8/8/r7/2R5 w - - 0 1", Color::Black));
+        assert!(!threat("4k3/8/r7/4PK2/8/8/8/7R b - - 0 1", Color::Black));
+    }
+
+    #[test]
+    fn knight_fork_squares() {
+        // Nxf7 hits the queen on d8 and the rook on h8, Ne6 the queen and the bishop on c5
+        let board = Game::new("rnbqk2r/pppp1ppp/5n2/2b1p1N1/2B1P3/8/PPPP1PPP/RNBQK2R w KQkq - 0 1")
+            .unwrap()
+            .board;
+        assert_eq!(
+            board.knight_fork_squares(Color::White),
+            square("f7") | square("e6")
+        );
+        assert_eq!(
+            Board::starting_position().knight_fork_squares(Color::White),
+            Bitboard(0)
+        );
+    }
+
+    #[test]
+    fn attacked_by_pawn_and_knight() {
+        let board = Game::new("4k3/8/3p4/8/8/5N2/8/4K3 w - - 0 1")
+            .unwrap()
+            .board;
+        assert!(board.attacked_by_pawn(square("e5"), Color::White));
+        assert!(board.attacked_by_pawn(square("c5"), Color::White));
+        assert!(!board.attacked_by_pawn(square("d5"), Color::White));
+        // The pawn only attacks downwards
+        assert!(!board.attacked_by_pawn(square("e7"), Color::White));
+        assert!(board.attacked_by_knight(square("e5"), Color::Black));
+        assert!(board.attacked_by_knight(square("g1"), Color::Black));
+        assert!(!board.attacked_by_knight(square("f4"), Color::Black));
+        assert!(!board.attacked_by_knight(square("e5"), Color::White));
+    }
+
+    #[test]
+    fn try_unmove_piece() {
+        let game = Game::new("1r2k3/2P5/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        let before = game.board;
+        for lan in ["c7b8q", "e1g1", "h1h5"] {
+            let mov = game.parse_move(lan).unwrap();
+            let mut board = before;
+            board.move_piece(mov);
+            assert_eq!(board.try_unmove_piece(mov), Ok(()));
+            // Castling rights are the caller's to restore
+            board.set_castling(before.castling);
+            assert_eq!(board, before);
+        }
+
+        // Undoing a move that was never made leaves the board alone
+        let mov = game.parse_move("h1h5").unwrap();
+        let mut board = game.board;
+        assert_eq!(
+            board.try_unmove_piece(mov),
+            Err(BoardError::PieceMismatch {
+                expected: mov.what.on_square(square("h5")),
+                found: None
+            })
+        );
+        assert_eq!(board, before);
+        assert_eq!(mov.reverse().lan_string(), "h5h1");
+        let promotion = game.parse_move("c7b8q").unwrap();
+        assert_eq!(promotion.reverse().what.kind, Kind::Queen);
+        assert_eq!(promotion.reverse().capture, None);
+    }
+
+    #[test]
+    fn control_map() {
+        let control = Board::starting_position().control_map();
+        // Both sides mirror each other
+        for idx in 0..64 {
+            assert_eq!(control[idx], -control[idx ^ 56], "{idx}");
+        }
+        // Two pawns on d3, two pawns and a knight on f3, nobody in the middle yet
+        assert_eq!(control[square("d3").idx()], 2);
+        assert_eq!(control[square("f3").idx()], 3);
+        assert_eq!(control[square("e4").idx()], 0);
+
+        // The knight and the pawn fight over d5 against the lone rook
+        let board = Game::new("3rk3/8/8/8/4P3/5N2/8/4K3 w - - 0 1")
+            .unwrap()
+            .board;
+        let control = board.control_map();
+        assert_eq!(control[square("d5").idx()], 0);
+        assert_eq!(control[square("d4").idx()], 0);
+        assert_eq!(control[square("e5").idx()], 1);
+    }
+
+    #[test]
+    fn generate_moves_for_color() {
+        let board = Board::starting_position();
+        assert_eq!(
+            board.generate_moves_for_color(Color::White).unwrap().len(),
+            20
+        );
+        let black = board.generate_moves_for_color(Color::Black).unwrap();
+        assert_eq!(black.len(), 20);
+        assert!(black.iter().all(|mov| mov.what.color == Color::Black));
+        assert_eq!(board.turn, Color::White);
+
+        let board = Game::new("4k3/8/8/8/8/8/8/8 w - - 0 1").unwrap().board;
+        assert_eq!(
+            board.generate_moves_for_color(Color::White),
+            Err(MovegenError::KingNotFound(Color::White))
+        );
+    }
+
+    #[test]
+    fn rook_moves_lose_castling_rights() {
+        let game = Game::new("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let lost = |lan: &str| game.parse_move(lan).unwrap().castling_rights_change;
+        assert_eq!(lost("a1a5"), CastlingRights::WHITE_QUEENSIDE);
+        assert_eq!(lost("h1h5"), CastlingRights::WHITE_KINGSIDE);
+        assert_eq!(lost("e1e2"), CastlingRights::WHITE_BOTH);
+
+        let mut castling = CastlingRights::ALL;
+        castling.update_on_rook_move(square("h8"));
+        castling.update_on_rook_move(square("d4"));
+        assert!(!castling.get_castling_right(CastlingRights::BLACK_KINGSIDE));
+        assert!(castling.get_castling_right(CastlingRights::BLACK_QUEENSIDE));
+        assert!(castling.get_castling_right(CastlingRights::WHITE_BOTH));
+    }
+
+    #[cfg(feature = "svg")]
+    #[test]
+    fn to_svg_string() {
+        let svg = Board::starting_position().to_svg_string();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.ends_with("</svg>"));
+        assert_eq!(svg.matches("<rect").count(), 64);
+        assert_eq!(svg.matches('♙').count(), 8);
+        assert_eq!(svg.matches('♚').count(), 1);
+    }
+
+    #[test]
+    fn legal_moves_count() {
+        for fen in [
+            Game::STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            // Double check from the rook and the knight
+            "4k3/8/5N2/8/8/8/8/K3R3 b - - 0 1",
+            // Fool's mate
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+            // Taking en passant would leave the king in check along the rank
+            "8/8/8/KPp4r/8/8/8/7k w - c6 0 2",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        ] {
+            let mut game = Game::new(fen).unwrap();
+            assert_eq!(
+                game.board.legal_moves_count(),
+                game.board.gen_legal_moves().unwrap().len(),
+                "{fen}"
+            );
+            // And every position a move away, with all their pins, checks and promotions
+            for mov in game.board.gen_legal_moves().unwrap() {
+                game.make_move(mov);
+                assert_eq!(
+                    game.board.legal_moves_count(),
+                    game.board.gen_legal_moves().unwrap().len(),
+                    "{fen} {mov}"
+                );
+                game.unmake_move(mov);
+            }
+        }
+    }
+
+    #[test]
+    fn check_blocks() {
+        let board = Game::new("4k3/8/8/8/1b6/8/8/4K3 w - - 0 1").unwrap().board;
+        let blocks = board.generate_check_blocks(square("b4"), Color::White);
+        assert_eq!(blocks, square("c3") | square("d2"));
+        // A knight check has to be answered by capturing it or moving the king
+        let board = Game::new("4k3/8/8/8/8/5n2/8/4K3 w - - 0 1").unwrap().board;
+        assert_eq!(
+            board.generate_check_blocks(square("f3"), Color::White),
+            Bitboard(0)
+        );
+    }
+
+    #[test]
+    fn checkmask() {
+        let game = Game::new("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(game.board.checkmask(Color::White), Bitboard::MAX);
+
+        // Block on e2-e7 or take the rook
+        let game = Game::new("4k3/4r3/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let blocks = square("e2") | square("e3") | square("e4") | square("e5") | square("e6");
+        assert_eq!(game.board.checkmask(Color::White), blocks | square("e7"));
+
+        // Knights can only be taken
+        let game = Game::new("4k3/8/8/8/8/3n4/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(game.board.checkmask(Color::White), square("d3"));
+
+        // Double check, only the king can move
+        let game = Game::new("4k3/4r3/8/8/8/3n4/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(game.board.checkmask(Color::White), Bitboard(0));
+    }
+
+    #[test]
+    fn single_ray_attacks() {
+        let occupancy = square("e1");
+        let east = square("b1") | square("c1") | square("d1") | square("e1");
+        assert_eq!(Bitboard::ray_attacks_east(square("a1"), occupancy), east);
+        assert_eq!(
+            Bitboard::ray_attacks_north(square("a1"), occupancy),
+            Bitboard::FILE_A & !square("a1")
+        );
+        assert_eq!(
+            Bitboard::ray_attacks_west(square("a1"), occupancy),
+            Bitboard(0)
+        );
+        assert_eq!(
+            Bitboard::ray_attacks_south(square("a1"), occupancy),
+            Bitboard(0)
+        );
+
+        type Ray = fn(Bitboard, Bitboard) -> Bitboard;
+        let rays: [(Ray, Direction); 8] = [
+            (Bitboard::ray_attacks_north, Direction::North),
+            (Bitboard::ray_attacks_south, Direction::South),
+            (Bitboard::ray_attacks_east, Direction::East),
+            (Bitboard::ray_attacks_west, Direction::West),
+            (Bitboard::ray_attacks_north_east, Direction::NorthEast),
+            (Bitboard::ray_attacks_north_west, Direction::NorthWest),
+            (Bitboard::ray_attacks_south_east, Direction::SouthEast),
+            (Bitboard::ray_attacks_south_west, Direction::SouthWest),
+        ];
+        let occupancy = square("c3") | square("f6") | square("e2") | square("b4");
+        for idx in 0..64 {
+            let square = Bitboard(1 << idx);
+            for (ray, direction) in rays {
+                assert_eq!(
+                    ray(square, occupancy),
+                    Board::ray_attacks(square, &[direction], occupancy),
+                    "{direction:?} from {idx}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn hyperbola_quintessence_matches_ray_tracing() {
+        // xorshift, so the occupancies are the same on every run
+        let mut seed: u64 = 0x9E37_79B9_7F4A_7C15;
+        for _ in 0..64 {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            // Sparse enough to leave some long rays
+            let occupancy = Bitboard(seed & (seed >> 3));
+            for idx in 0..64 {
+                let square = Bitboard(1 << idx);
+                assert_eq!(
+                    Board::rook_attacks_on_rank(square, occupancy),
+                    Board::ray_attacks(square, &[Direction::East, Direction::West], occupancy),
+                    "rank attacks from {idx} with occupancy {occupancy}"
+                );
+                assert_eq!(
+                    Board::rook_attacks_on_file(square, occupancy),
+                    Board::ray_attacks(square, &[Direction::North, Direction::South], occupancy),
+                    "file attacks from {idx} with occupancy {occupancy}"
+                );
+                assert_eq!(
+                    Board::rook_attacks_hq(square, occupancy),
+                    Board::ray_attacks(square, &Direction::STRAIGHT_MOVES, occupancy),
+                    "rook on {idx} with occupancy {occupancy}"
+                );
+                assert_eq!(
+                    Board::bishop_attacks_hq(square, occupancy),
+                    Board::ray_attacks(square, &Direction::DIAGONAL_MOVES, occupancy),
+                    "bishop on {idx} with occupancy {occupancy}"
+                );
+                assert_eq!(
+                    Board::sliding_attacks_from(square, Kind::Queen, occupancy),
+                    Board::ray_attacks(square, &Direction::SLIDING_MOVES, occupancy),
+                    "queen on {idx} with occupancy {occupancy}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn index_bounds() {
+        let corners = square("a1") | square("h8");
+        assert!(corners[0]);
+        assert!(corners[63]);
+        assert!(!corners[62]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Square index 64 out of range")]
+    fn index_past_h8() {
+        // Would wrap around to a1 with a plain shift in release builds
+        let _ = Bitboard(1)[64];
+    }
+
+    #[test]
+    #[should_panic(expected = "Knight is not a sliding piece")]
+    fn sliding_attacks_from_knight() {
+        Board::sliding_attacks_from(square("b1"), Kind::Knight, Bitboard(0));
+    }
+
+    #[test]
+    fn insufficient_material_for_color() {
+        let cant_mate = |fen: &str, color: Color| {
+            Game::new(fen)
+                .unwrap()
+                .board
+                .is_insufficient_material_for_color(color)
+        };
+        // A bare king never mates, the side with the queen always can
+        assert!(cant_mate("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", Color::Black));
+        assert!(!cant_mate("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", Color::White));
+        // A lone knight against a bare king, but not against a king that can get in its own way
+        assert!(cant_mate("4k3/8/8/8/8/8/8/3NK3 w - - 0 1", Color::White));
+        assert!(!cant_mate("4k3/4r3/8/8/8/8/8/3NK3 w - - 0 1", Color::White));
+        assert!(!cant_mate("4k3/8/8/8/8/8/8/2NNK3 w - - 0 1", Color::White));
+        assert!(!cant_mate("4k3/8/8/8/8/8/8/3BK2P w - - 0 1", Color::White));
+    }
+
+    #[test]
+    fn insufficient_material() {
+        let is_draw = |fen: &str| {
+            Game::new(fen)
+                .unwrap()
+                .board
+                .is_draw_by_insufficient_material()
+        };
+        // KK
+        assert!(is_draw("8/8/4k3/8/8/4K3/8/8 w - - 0 1"));
+        // KNK, either side
+        assert!(is_draw("8/8/4k3/8/8/4KN2/8/8 w - - 0 1"));
+        assert!(is_draw("8/8/4kn2/8/8/4K3/8/8 w - - 0 1"));
+        // KBK, either side
+        assert!(is_draw("8/8/4k3/8/8/4KB2/8/8 w - - 0 1"));
+        assert!(is_draw("8/8/4kb2/8/8/4K3/8/8 w - - 0 1"));
+        // KBKB, bishops on the same color (f3 and c6 are both light)
+        assert!(is_draw("8/8/2b1k3/8/8/4KB2/8/8 w - - 0 1"));
+
+        // KBKB, opposite colors
+        assert!(!is_draw("8/8/1b2k3/8/8/4KB2/8/8 w - - 0 1"));
+        // KRK
+        assert!(!is_draw("8/8/4k3/8/8/4KR2/8/8 w - - 0 1"));
+        // KBBK, opposite colors
+        assert!(!is_draw("8/8/4k3/8/8/4KBB1/8/8 w - - 0 1"));
+        // KBNK
+        assert!(!is_draw("8/8/4k3/8/8/4KBN1/8/8 w - - 0 1"));
+        // KPK
+        assert!(!is_draw("8/8/4k3/8/8/4KP2/8/8 w - - 0 1"));
+
+        // KNNK only when asked for
+        let game = Game::new("8/8/4k3/8/8/4KNN1/8/8 w - - 0 1").unwrap();
+        assert!(!game.board.is_draw_by_insufficient_material());
+        assert!(game.board.is_insufficient_material(true));
+    }
+}
@@ -1 +1,311 @@
+use crate::{
+    board::Board,
+    piece::{Color, Kind},
+};
 
+pub const PAWN_VALUE: i32 = 100;
+pub const KNIGHT_VALUE: i32 = 320;
+pub const BISHOP_VALUE: i32 = 330;
+pub const ROOK_VALUE: i32 = 500;
+pub const QUEEN_VALUE: i32 = 900;
+
+// Score of being checkmated, well outside anything the evaluation can reach
+pub const MATE_SCORE: i32 = 100_000;
+
+const HANGING_PIECE_PENALTY: i32 = 50;
+// Per rank of advancement, so deeper outposts are worth more
+const OUTPOST_RANK_BONUS: i32 = 5;
+const CONNECTED_ROOKS_BONUS: i32 = 15;
+const ROOK_ON_SEVENTH_BONUS: i32 = 20;
+const BISHOP_PAIR_BONUS: i32 = 50;
+// A rook or queen away from mating on the back rank
+const BACK_RANK_THREAT_PENALTY: i32 = 200;
+
+// Piece-square tables, indexed by `Kind`. Laid out the way the board is printed, rank 8 first and
+// from White's side, so White's squares get flipped vertically before the lookup
+#[rustfmt::skip]
+const MIDDLEGAME_TABLES: [[i32; 64]; 6] = [
+    // Pawn
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+         50,  50,  50,  50,  50,  50,  50,  50,
+         10,  10,  20,  30,  30,  20,  10,  10,
+          5,   5,  10,  25,  25,  10,   5,   5,
+          0,   0,   0,  20,  20,   0,   0,   0,
+          5,  -5, -10,   0,   0, -10,  -5,   5,
+          5,  10,  10, -20, -20,  10,  10,   5,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    // Knight
+    [
+        -50, -40, -30, -30, -30, -30, -40, -50,
+        -40, -20,   0,   0,   0,   0, -20, -40,
+        -30,   0,  10,  15,  15,  10,   0, -30,
+        -30,   5,  15,  20,  20,  15,   5, -30,
+        -30,   0,  15,  20,  20,  15,   0, -30,
+        -30,   5,  10,  15,  15,  10,   5, -30,
+        -40, -20,   0,   5,   5,   0, -20, -40,
+        -50, -40, -30, -30, -30, -30, -40, -50,
+    ],
+    // Bishop
+    [
+        -20, -10, -10, -10, -10, -10, -10, -20,
+        -10,   0,   0,   0,   0,   0,   0, -10,
+        -10,   0,   5,  10,  10,   5,   0, -10,
+        -10,   5,   5,  10,  10,   5,   5, -10,
+        -10,   0,  10,  10,  10,  10,   0, -10,
+        -10,  10,  10,  10,  10,  10,  10, -10,
+        -10,   5,   0,   0,   0,   0,   5, -10,
+        -20, -10, -10, -10, -10, -10, -10, -20,
+    ],
+    // Rook
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+          5,  10,  10,  10,  10,  10,  10,   5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+          0,   0,   0,   5,   5,   0,   0,   0,
+    ],
+    // Queen
+    [
+        -20, -10, -10,  -5,  -5, -10, -10, -20,
+        -10,   0,   0,   0,   0,   0,   0, -10,
+        -10,   0,   5,   5,   5,   5,   0, -10,
+         -5,   0,   5,   5,   5,   5,   0,  -5,
+          0,   0,   5,   5,   5,   5,   0,  -5,
+        -10,   5,   5,   5,   5,   5,   0, -10,
+        -10,   0,   5,   0,   0,   0,   0, -10,
+        -20, -10, -10,  -5,  -5, -10, -10, -20,
+    ],
+    // King, tucked away behind its pawns
+    [
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -20, -30, -30, -40, -40, -30, -30, -20,
+        -10, -20, -20, -20, -20, -20, -20, -10,
+         20,  20,   0,   0,   0,   0,  20,  20,
+         20,  30,  10,   0,   0,  10,  30,  20,
+    ],
+];
+
+// Pawns race for promotion and the king comes out to the center
+#[rustfmt::skip]
+const ENDGAME_TABLES: [[i32; 64]; 6] = [
+    // Pawn
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+         80,  80,  80,  80,  80,  80,  80,  80,
+         50,  50,  50,  50,  50,  50,  50,  50,
+         30,  30,  30,  30,  30,  30,  30,  30,
+         20,  20,  20,  20,  20,  20,  20,  20,
+         10,  10,  10,  10,  10,  10,  10,  10,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    MIDDLEGAME_TABLES[Kind::Knight as usize],
+    MIDDLEGAME_TABLES[Kind::Bishop as usize],
+    // Rook
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+          5,  10,  10,  10,  10,  10,  10,   5,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    MIDDLEGAME_TABLES[Kind::Queen as usize],
+    // King
+    [
+        -50, -40, -30, -20, -20, -30, -40, -50,
+        -30, -20, -10,   0,   0, -10, -20, -30,
+        -30, -10,  20,  30,  30,  20, -10, -30,
+        -30, -10,  30,  40,  40,  30, -10, -30,
+        -30, -10,  30,  40,  40,  30, -10, -30,
+        -30, -10,  20,  30,  30,  20, -10, -30,
+        -30, -30,   0,   0,   0,   0, -30, -30,
+        -50, -30, -30, -30, -30, -30, -30, -50,
+    ],
+];
+
+// Phase weights of the pieces left on the board. A full set adds up to `MAX_PHASE`, pawns and
+// kings don't count
+const KNIGHT_PHASE: i32 = 1;
+const BISHOP_PHASE: i32 = 1;
+const ROOK_PHASE: i32 = 2;
+const QUEEN_PHASE: i32 = 4;
+const MAX_PHASE: i32 = 24;
+
+pub const fn piece_value(kind: Kind) -> i32 {
+    match kind {
+        Kind::Pawn => PAWN_VALUE,
+        Kind::Knight => KNIGHT_VALUE,
+        Kind::Bishop => BISHOP_VALUE,
+        Kind::Rook => ROOK_VALUE,
+        Kind::Queen => QUEEN_VALUE,
+        Kind::King => 0,
+    }
+}
+
+// Score in centipawns from the point of view of the side to move
+pub fn evaluate(board: &Board) -> i32 {
+    let score = evaluate_color(board, Color::White) - evaluate_color(board, Color::Black);
+    match board.turn {
+        Color::White => score,
+        Color::Black => -score,
+    }
+}
+
+// How much of the middlegame is left, from `MAX_PHASE` with every piece on the board down to 0
+// with only pawns and kings
+pub fn game_phase(board: &Board) -> i32 {
+    let phase = board.knights.count() as i32 * KNIGHT_PHASE
+        + board.bishops.count() as i32 * BISHOP_PHASE
+        + board.rooks.count() as i32 * ROOK_PHASE
+        + board.queens.count() as i32 * QUEEN_PHASE;
+    phase.min(MAX_PHASE)
+}
+
+// Piece-square bonus for `color`, blended between the middlegame and endgame tables by `phase`
+pub fn piece_square_score(board: &Board, color: Color, phase: i32) -> i32 {
+    let mut middlegame = 0;
+    let mut endgame = 0;
+    for piece in board.piece_list_for(color) {
+        let idx = match color {
+            Color::White => piece.position.idx() ^ 56,
+            Color::Black => piece.position.idx(),
+        };
+        middlegame += MIDDLEGAME_TABLES[piece.kind as usize][idx];
+        endgame += ENDGAME_TABLES[piece.kind as usize][idx];
+    }
+    (middlegame * phase + endgame * (MAX_PHASE - phase)) / MAX_PHASE
+}
+
+// Sum of the values of `color`'s pieces
+pub fn material(board: &Board, color: Color) -> i32 {
+    [
+        Kind::Pawn,
+        Kind::Knight,
+        Kind::Bishop,
+        Kind::Rook,
+        Kind::Queen,
+    ]
+    .into_iter()
+    .map(|kind| piece_value(kind) * board.get_pieces(kind, color).count() as i32)
+    .sum()
+}
+
+fn evaluate_color(board: &Board, color: Color) -> i32 {
+    let mut score = material(board, color) + piece_square_score(board, color, game_phase(board));
+
+    for square in board.get_pieces(Kind::Knight, color) | board.get_pieces(Kind::Bishop, color) {
+        if board.is_outpost(square, color) {
+            let rank = match color {
+                Color::White => square.idx() / 8,
+                Color::Black => 7 - square.idx() / 8,
+            };
+            score += OUTPOST_RANK_BONUS * rank as i32;
+        }
+    }
+
+    if board.connected_rooks(color) {
+        score += CONNECTED_ROOKS_BONUS;
+    }
+    if board.rook_on_seventh(color) {
+        score += ROOK_ON_SEVENTH_BONUS;
+    }
+    if board.has_bishop_pair(color) {
+        score += BISHOP_PAIR_BONUS;
+    }
+    if board.detect_back_rank_mate_threat(color) {
+        score -= BACK_RANK_THREAT_PENALTY;
+    }
+
+    // The king being attacked is a check, not a hanging piece
+    for square in board.threatened_pieces(color) & !board.kings {
+        if board.is_piece_hanging(square, color) {
+            score -= HANGING_PIECE_PENALTY;
+        }
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Game;
+
+    fn evaluate_fen(fen: &str) -> i32 {
+        evaluate(&Game::new(fen).unwrap().board)
+    }
+
+    #[test]
+    fn starting_position_is_even() {
+        assert_eq!(evaluate_fen(Game::STARTING_FEN), 0);
+        assert_eq!(game_phase(&Board::starting_position()), MAX_PHASE);
+    }
+
+    #[test]
+    fn free_queen() {
+        // Black left the queen on d5 and it's White's turn to take it
+        let white = evaluate_fen("rnb1kbnr/pppp1ppp/8/3qp3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3");
+        // The same after the capture, a queen up
+        let black = evaluate_fen("rnb1kbnr/pppp1ppp/8/3Pp3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 3");
+        assert!(black < -QUEEN_VALUE + 200, "{black}");
+        // Before the capture the queen is only hanging
+        assert!(white > -200 && white < 200, "{white}");
+    }
+
+    #[test]
+    fn connected_rooks() {
+        let board = |fen: &str| Game::new(fen).unwrap().board;
+        // Along the first rank, and along the a file
+        assert!(board("4k3/8/8/8/8/8/8/R4RK1 w - - 0 1").connected_rooks(Color::White));
+        assert!(board("4k3/8/8/8/R7/8/8/R3K3 w - - 0 1").connected_rooks(Color::White));
+        // The king stands between them, or there's only the one rook
+        assert!(!board("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1").connected_rooks(Color::White));
+        assert!(!board("r3k3/8/8/8/8/8/8/R3K3 w - - 0 1").connected_rooks(Color::Black));
+    }
+
+    #[test]
+    fn rook_on_seventh() {
+        let board = |fen: &str| Game::new(fen).unwrap().board;
+        assert!(board("4k3/R7/8/8/8/8/8/4K3 w - - 0 1").rook_on_seventh(Color::White));
+        assert!(board("4k3/8/8/8/8/8/r7/4K3 w - - 0 1").rook_on_seventh(Color::Black));
+        // Each side's seventh is the other's second
+        assert!(!board("4k3/8/8/8/8/8/R7/4K3 w - - 0 1").rook_on_seventh(Color::White));
+        assert!(!board("4k3/r7/8/8/8/8/8/4K3 w - - 0 1").rook_on_seventh(Color::Black));
+    }
+
+    #[test]
+    fn piece_square_tables() {
+        // Knights in the center beat knights on the rim
+        let center = Game::new("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1").unwrap().board;
+        let rim = Game::new("4k3/8/8/8/N7/8/8/4K3 w - - 0 1").unwrap().board;
+        let phase = game_phase(&center);
+        assert!(
+            piece_square_score(&center, Color::White, phase)
+                > piece_square_score(&rim, Color::White, phase)
+        );
+        // With nothing but kings and pawns the king wants the center, not the corner
+        let endgame = Game::new("4k3/8/8/8/3K4/8/8/8 w - - 0 1").unwrap().board;
+        let corner = Game::new("4k3/8/8/8/8/8/8/6K1 w - - 0 1").unwrap().board;
+        assert_eq!(game_phase(&endgame), 0);
+        assert!(
+            piece_square_score(&endgame, Color::White, 0)
+                > piece_square_score(&corner, Color::White, 0)
+        );
+        // Mirrored squares score the same for both colors
+        let black = Game::new("4k3/8/8/3n4/8/8/8/4K3 w - - 0 1").unwrap().board;
+        assert_eq!(
+            piece_square_score(&center, Color::White, phase),
+            piece_square_score(&black, Color::Black, phase)
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use std::error::Error;
 
+use crate::eval::{evaluate, material, MATE_SCORE};
 use crate::history::HistoryItem;
 use crate::move_generation::Movegen;
 use crate::{
@@ -12,18 +13,30 @@ use crate::{
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Game {
     pub board: Board,
     pub is_in_check: bool,
+    // The current position has been on the board three times, which draws the game
+    pub is_repetition: bool,
     pub history: History,
     pub halfmove_clock: u8,
     pub fullmove_number: u16,
+    // Position the game was set up from, to replay the history
+    pub root_fen: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FenError {
     InvalidFen(String, char),
     InvalidEnPassant(String),
+    InvalidColor(String),
+    // Fewer than the four fields that can't be left out
+    MissingFields(String),
+    InvalidCastling(String, char),
+    InvalidMoveCounter(String),
+    // Parses, but the moves can't be generated or undone from it
+    IllegalPosition(String, Vec<String>),
 }
 
 impl From<BitboardError> for FenError {
@@ -44,29 +57,113 @@ impl std::fmt::Display for FenError {
                     "Invalid FEN string: {en_passant}, invalid en passant square"
                 )
             }
+            Self::InvalidColor(color) => {
+                write!(f, "Invalid FEN string: {color}, invalid active color")
+            }
+            Self::MissingFields(fen) => {
+                write!(
+                    f,
+                    "Invalid FEN string: {fen}, expected at least four fields"
+                )
+            }
+            Self::InvalidCastling(fen, c) => {
+                write!(f, "Invalid FEN string: {fen}, invalid castling right: {c}")
+            }
+            Self::InvalidMoveCounter(counter) => {
+                write!(f, "Invalid FEN string: {counter}, invalid move counter")
+            }
+            Self::IllegalPosition(fen, violations) => {
+                write!(f, "Illegal position: {fen}, {}", violations.join(", "))
+            }
         }
     }
 }
 impl Error for FenError {}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl std::fmt::Display for GameResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::WhiteWins => write!(f, "1-0"),
+            Self::BlackWins => write!(f, "0-1"),
+            Self::Draw => write!(f, "1/2-1/2"),
+        }
+    }
+}
+
+// Snapshot of the numbers behind a position, for checking evaluation terms and reporting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameStats {
+    pub white_material: i32,
+    pub black_material: i32,
+    pub white_mobility: u32,
+    pub black_mobility: u32,
+    pub white_passed_pawns: u8,
+    pub black_passed_pawns: u8,
+    pub white_doubled_pawns: u8,
+    pub black_doubled_pawns: u8,
+    pub in_check: bool,
+    pub ply: u16,
+}
+
 impl Game {
     pub const STARTING_FEN: &'static str =
         "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
     pub fn new(fen: &str) -> Result<Self, FenError> {
+        let mut game = Game {
+            board: Board::new(),
+            history: History::default(),
+            is_in_check: false,
+            is_repetition: false,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            root_fen: String::new(),
+        };
+        game.set_position(fen)?;
+        Ok(game)
+    }
+
+    // Resets the game to `fen` in place, keeping the history's allocation around. On error the
+    // game is left as it was
+    pub fn set_position(&mut self, fen: &str) -> Result<(), FenError> {
         let mut board = Board::new();
         let mut rank = 7;
         let mut file = 0;
         let splitted_vec = fen.split(' ').collect::<Vec<&str>>();
-        assert!(splitted_vec.len() >= 4); // halfmove clock, fullmove number can be omitted
+        // halfmove clock, fullmove number can be omitted
+        if splitted_vec.len() < 4 {
+            return Err(FenError::MissingFields(fen.to_string()));
+        }
         let mut splitted_iter = splitted_vec.into_iter();
-        let pieces = splitted_iter.next().map_or_else(
-            || {
-                panic!("Invalid FEN string: {fen}");
-            },
-            |pieces| pieces,
-        );
+        let pieces = splitted_iter
+            .next()
+            .unwrap_or_else(|| panic!("Invalid FEN string: {fen}"));
 
         for c in pieces.chars() {
+            // Anything past the h file or below the first rank would land off the board
+            if (file >= 8 && c != '/') || (rank == 0 && c == '/') {
+                return Err(FenError::InvalidFen(fen.to_string(), c));
+            }
+            // The board only keeps track of one king per side
+            let king_color = match c {
+                'K' => Some(Color::White),
+                'k' => Some(Color::Black),
+                _ => None,
+            };
+            if let Some(color) = king_color {
+                if !board.get_pieces(Kind::King, color).is_empty() {
+                    return Err(FenError::IllegalPosition(
+                        fen.to_string(),
+                        vec![format!("{color} has more than one king")],
+                    ));
+                }
+            }
             match c {
                 'P' | 'N' | 'B' | 'R' | 'Q' | 'K' => {
                     board.spawn_piece(Piece::new(
@@ -111,14 +208,7 @@ impl Game {
             }
         }
 
-        let turn = match splitted_iter.next().unwrap() {
-            "w" => Color::White,
-            "b" => Color::Black,
-            _ => {
-                panic!("Invalid FEN string: {fen}");
-            }
-        };
-        board.turn = turn;
+        board.turn = Color::from_fen_str(splitted_iter.next().unwrap())?;
 
         let castling_rights = splitted_iter.next().unwrap();
 
@@ -131,7 +221,7 @@ impl Game {
                 'k' => set_castling_right(CastlingRights::BLACK_KINGSIDE),
                 'q' => set_castling_right(CastlingRights::BLACK_QUEENSIDE),
                 '-' => (),
-                _ => panic!("Invalid FEN string: {fen}"),
+                _ => return Err(FenError::InvalidCastling(fen.to_string(), c)),
             }
         }
 
@@ -142,74 +232,790 @@ impl Game {
         } else {
             Some(Bitboard::from_algebraic(en_passant_str)?)
         };
+        board.refresh_hash();
+        let violations = board.state_violations();
+        if !violations.is_empty() {
+            return Err(FenError::IllegalPosition(fen.to_string(), violations));
+        }
 
         let halfmove_clock = match splitted_iter.next() {
-            Some(halfmove_clock) => halfmove_clock.parse().unwrap(),
+            Some(halfmove_clock) => halfmove_clock
+                .parse()
+                .map_err(|_| FenError::InvalidMoveCounter(halfmove_clock.to_string()))?,
             None => 0,
         };
 
         let fullmove_number = match splitted_iter.next() {
-            Some(fullmove_number) => fullmove_number.parse().unwrap(),
+            Some(fullmove_number) => fullmove_number
+                .parse()
+                .map_err(|_| FenError::InvalidMoveCounter(fullmove_number.to_string()))?,
             None => 1,
         };
 
-        Ok(Game {
-            board,
-            history: History(vec![]),
-            is_in_check: false,
-            halfmove_clock,
-            fullmove_number,
-        })
+        self.board = board;
+        self.history.reset(self.board.hash());
+        self.is_in_check = false;
+        self.is_repetition = false;
+        self.halfmove_clock = halfmove_clock;
+        self.fullmove_number = fullmove_number;
+        self.root_fen.clear();
+        self.root_fen.push_str(fen);
+        Ok(())
+    }
+
+    // Full six-field FEN of the current position. `Game::new` on the result gives back an equal
+    // game, short of the history
+    pub fn to_fen(&self) -> String {
+        self.board.fen_snapshot(
+            self.halfmove_clock,
+            self.fullmove_number,
+            self.board.en_passant,
+            self.board.castling,
+        )
+    }
+
+    // FEN of the root position followed by the position after each move in the history. Walks
+    // the history backwards, taking the state fields from each item, so nothing gets replayed
+    pub fn all_fen_history(&self) -> Vec<String> {
+        let mut board = self.board;
+        let mut fullmove_number = self.fullmove_number;
+        let mut fens = vec![self.to_fen()];
+        for item in self.history.iter().rev() {
+            board.unmove_piece(item.r#move);
+            board.flip_turn();
+            if board.turn == Color::Black {
+                fullmove_number -= 1;
+            }
+            fens.push(board.fen_snapshot(
+                item.prior_halfmove_clock,
+                fullmove_number,
+                item.prior_en_passant,
+                item.prior_castling,
+            ));
+        }
+        fens.reverse();
+        fens
+    }
+
+    // Root FEN and the moves played since, in long algebraic notation. Enough to send the game
+    // as a UCI `position fen <fen> moves <moves>` command
+    pub fn current_fen_with_history(&self) -> (String, Vec<String>) {
+        let moves = self
+            .history
+            .iter()
+            .map(|item| item.r#move.lan_string())
+            .collect();
+        (self.root_fen.clone(), moves)
+    }
+
+    // Half-moves played since the game was set up
+    pub fn ply(&self) -> u16 {
+        self.history.len() as u16
+    }
+
+    pub fn turn(&self) -> Color {
+        self.board.turn
     }
 
     pub fn make_move(&mut self, mov: Move) {
-        self.board.move_piece(mov);
+        self.make_move_unchecked(mov);
+        let mover = !self.board.turn;
 
-        self.history.push(HistoryItem {
-            r#move: mov,
-            squares_attacked: self.board.attacked_squares,
-        });
-        self.fullmove_number += 1;
-        self.halfmove_clock += 1;
-        self.is_in_check = self.board.is_check(self.board.turn);
+        // A side without a king can't be in check
+        self.is_in_check = self.board.is_check(mover).unwrap_or(false);
 
         if self.is_in_check {
             // remove castling rights to the color in check
             // println!("{} is in check, removing castling rights ({})", self.turn, mov);
-            match !self.board.turn {
-                Color::White => self
-                    .board
-                    .castling
-                    .set_castling_right(CastlingRights::WHITE_BOTH, false),
-                Color::Black => self
-                    .board
-                    .castling
-                    .set_castling_right(CastlingRights::BLACK_BOTH, false),
+            let mut castling = self.board.castling;
+            match self.board.turn {
+                Color::White => castling.set_castling_right(CastlingRights::WHITE_BOTH, false),
+                Color::Black => castling.set_castling_right(CastlingRights::BLACK_BOTH, false),
             }
+            self.board.set_castling(castling);
+        }
+    }
+
+    // Same as `make_move`, but leaves `is_in_check` untouched. For callers like perft that do
+    // their own check detection
+    pub fn make_move_unchecked(&mut self, mov: Move) {
+        let prior_en_passant = self.board.en_passant;
+        let prior_castling = self.board.castling;
+        let prior_halfmove_clock = self.halfmove_clock;
+        self.board.move_piece(mov);
+
+        // The fullmove number goes up after Black's move
+        if self.board.turn == Color::Black {
+            self.fullmove_number += 1;
+        }
+        // Pawn moves and captures are irreversible, so the fifty-move count starts over
+        if mov.what.kind == Kind::Pawn || mov.capture.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock = self.halfmove_clock.saturating_add(1);
         }
 
         self.board.flip_turn();
+        self.history.push(HistoryItem {
+            r#move: mov,
+            squares_attacked: self.board.attacked_squares,
+            prior_halfmove_clock,
+            prior_en_passant,
+            prior_castling,
+            hash: self.board.hash(),
+        });
+        self.is_repetition = self.history.is_repetition(self.board.hash());
     }
 
     pub fn unmake_move(&mut self, mov: Move) {
         // let mov = game.history.pop().expect("No moves to undo");
-        self.history.pop().expect("No moves to undo");
-        self.board.unmove_piece(mov);
+        let item = self.history.pop().expect("No moves to undo");
+        // Checking the board first costs too much to do on every unmake in release builds
+        if cfg!(debug_assertions) {
+            if let Err(err) = self.board.try_unmove_piece(mov) {
+                panic!("Couldn't unmake {mov}: {err}\n{}", self.board);
+            }
+        } else {
+            self.board.unmove_piece(mov);
+        }
+        self.board.set_en_passant(item.prior_en_passant);
+        self.board.set_castling(item.prior_castling);
         self.board.flip_turn();
-        self.fullmove_number -= 1;
-        self.halfmove_clock -= 1;
+        if self.board.turn == Color::Black {
+            self.fullmove_number -= 1;
+        }
+        self.halfmove_clock = item.prior_halfmove_clock;
+        self.is_repetition = self.history.is_repetition(self.board.hash());
+    }
+
+    pub fn statistics(&self) -> GameStats {
+        // Legal moves `color` would have if it were their turn
+        let mobility = |color: Color| {
+            let mut board = self.board;
+            if board.turn != color {
+                board.flip_turn();
+                board.set_en_passant(None);
+            }
+            board
+                .gen_legal_moves()
+                .map_or(0, |moves| moves.len() as u32)
+        };
+        let count_pawns = |color: Color, test: fn(&Board, Bitboard, Color) -> bool| {
+            self.board
+                .get_pieces(Kind::Pawn, color)
+                .filter(|&square| test(&self.board, square, color))
+                .count() as u8
+        };
+        GameStats {
+            white_material: material(&self.board, Color::White),
+            black_material: material(&self.board, Color::Black),
+            white_mobility: mobility(Color::White),
+            black_mobility: mobility(Color::Black),
+            white_passed_pawns: count_pawns(Color::White, Board::is_passed_pawn),
+            black_passed_pawns: count_pawns(Color::Black, Board::is_passed_pawn),
+            white_doubled_pawns: count_pawns(Color::White, Board::is_doubled_pawn),
+            black_doubled_pawns: count_pawns(Color::Black, Board::is_doubled_pawn),
+            in_check: self.board.in_check(),
+            ply: self.ply(),
+        }
+    }
+
+    // Score in centipawns for the side to move, with game endings taken into account
+    pub fn score_position(&self) -> i32 {
+        match self.result() {
+            // Only the side that just moved can have delivered mate
+            Some(GameResult::WhiteWins | GameResult::BlackWins) => -MATE_SCORE,
+            Some(GameResult::Draw) => 0,
+            None if self.is_draw() => 0,
+            None => evaluate(&self.board),
+        }
+    }
+
+    // Drawn on the board, by threefold repetition or by the fifty-move rule
+    pub fn is_draw(&self) -> bool {
+        self.is_fifty_move_draw() || self.is_repetition || self.result() == Some(GameResult::Draw)
+    }
+
+    // Fifty moves each without a pawn move or a capture
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.halfmove_clock >= 100
+    }
+
+    // In check with no legal move
+    pub fn is_checkmate(&mut self) -> bool {
+        self.board.in_check() && !self.has_legal_move()
+    }
+
+    // Not in check, but no legal move either
+    pub fn is_stalemate(&mut self) -> bool {
+        !self.board.in_check() && !self.has_legal_move()
+    }
+
+    // Plays each pseudo-legal move until one doesn't leave the mover's king in check
+    fn has_legal_move(&mut self) -> bool {
+        let mover = self.board.turn;
+        for mov in self.board.gen_moves().unwrap_or_default() {
+            self.make_move_unchecked(mov);
+            let legal = !self.board.is_check(mover).unwrap_or(true);
+            self.unmake_move(mov);
+            if legal {
+                return true;
+            }
+        }
+        false
+    }
+
+    // Outcome of the game if it's over on the board: checkmate, stalemate or insufficient material
+    pub fn result(&self) -> Option<GameResult> {
+        self.board.king_position(self.board.turn).ok()?;
+        if self.board.legal_moves_count() == 0 {
+            return Some(
+                match (self.board.is_check(self.board.turn), self.board.turn) {
+                    (Ok(true), Color::White) => GameResult::BlackWins,
+                    (Ok(true), Color::Black) => GameResult::WhiteWins,
+                    _ => GameResult::Draw,
+                },
+            );
+        }
+        if self.board.is_draw_by_insufficient_material() {
+            return Some(GameResult::Draw);
+        }
+        None
+    }
+
+    // Standard algebraic notation of a legal move in the current position, with disambiguation
+    // and check or mate markers
+    pub fn san(&self, mov: Move) -> String {
+        let mut san = mov.san_preview();
+
+        // Another piece of the same kind that could go to the same square
+        if mov.castle_move.is_none() && mov.what.kind != Kind::Pawn {
+            let rivals = self
+                .board
+                .gen_legal_moves()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|other| {
+                    other.what.kind == mov.what.kind && other.to == mov.to && other.from != mov.from
+                })
+                .map(|other| other.from)
+                .collect::<Vec<Bitboard>>();
+            if !rivals.is_empty() {
+                let from = mov.from.to_algebraic().unwrap_or_else(|_| "EE".to_string());
+                let same_file = rivals
+                    .iter()
+                    .any(|rival| rival.file_mask() == mov.from.file_mask());
+                let same_rank = rivals
+                    .iter()
+                    .any(|rival| rival.rank_mask() == mov.from.rank_mask());
+                let disambiguation = if !same_file {
+                    &from[..1]
+                } else if !same_rank {
+                    &from[1..]
+                } else {
+                    &from
+                };
+                san.insert_str(1, disambiguation);
+            }
+        }
+
+        let mut after = self.board;
+        after.move_piece(mov);
+        after.flip_turn();
+        if after.in_check() {
+            let is_mate = after.legal_moves_count() == 0;
+            san.push(if is_mate { '#' } else { '+' });
+        }
+        san
+    }
+
+    // PGN movetext for the whole history, replayed from the root position, ending with the
+    // result token
+    pub fn movelist_to_pgn_movetext(&self) -> String {
+        let mut game = Self::new(&self.root_fen).expect("Root FEN was already parsed once");
+        let mut tokens = vec![];
+        for (ply, item) in self.history.iter().enumerate() {
+            match game.turn() {
+                Color::White => tokens.push(format!("{}.", game.fullmove_number)),
+                Color::Black if ply == 0 => tokens.push(format!("{}...", game.fullmove_number)),
+                Color::Black => {}
+            }
+            tokens.push(game.san(item.r#move));
+            game.make_move(item.r#move);
+        }
+        tokens.push(
+            game.result()
+                .map_or("*".to_string(), |result| result.to_string()),
+        );
+        tokens.join(" ")
     }
 
     pub fn parse_move(&self, r#move: &str) -> Result<Move, MovegenError> {
         // println!("Parsing move: {}", r#move);
         let from = Bitboard::from_algebraic(&r#move[0..2])?;
         let to = Bitboard::from_algebraic(&r#move[2..4])?;
+        // The promotion piece, if given, picks among the four moves to the last rank
+        let promotion = match r#move.get(4..) {
+            None | Some("") => None,
+            Some("q") => Some(Kind::Queen),
+            Some("r") => Some(Kind::Rook),
+            Some("b") => Some(Kind::Bishop),
+            Some("n") => Some(Kind::Knight),
+            Some(_) => return Err(MovegenError::InvalidMove(r#move.to_string())),
+        };
         let legal_moves = self.board.gen_moves()?;
         for legal_move in legal_moves {
-            if legal_move.from == from && legal_move.to == to {
+            if legal_move.from == from
+                && legal_move.to == to
+                && promotion.is_none_or(|kind| legal_move.promotion == Some(kind))
+            {
                 return Ok(legal_move);
             }
         }
         Err(MovegenError::InvalidMove(r#move.to_string()))
     }
 }
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new(Self::STARTING_FEN).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(algebraic: &str) -> Bitboard {
+        Bitboard::from_algebraic(algebraic).unwrap()
+    }
+
+    #[test]
+    fn en_passant_back_to_back() {
+        let mut game = Game::new("4k3/5p2/8/4P3/3p4/8/4P3/4K3 w - - 0 1").unwrap();
+
+        let push = game.parse_move("e2e4").unwrap();
+        game.make_move(push);
+        assert_eq!(game.board.en_passant, Some(square("e3")));
+
+        // Black ignores dxe3 and offers exf6 instead
+        let black_push = game.parse_move("f7f5").unwrap();
+        game.make_move(black_push);
+        assert_eq!(game.board.en_passant, Some(square("f6")));
+        assert!(game.parse_move("e5f6").unwrap().capture.is_some());
+
+        let capture = game.parse_move("e5f6").unwrap();
+        game.make_move(capture);
+        assert_eq!(game.board.en_passant, None);
+
+        // Unmaking brings back each target in turn
+        game.unmake_move(capture);
+        assert_eq!(game.board.en_passant, Some(square("f6")));
+        game.unmake_move(black_push);
+        assert_eq!(game.board.en_passant, Some(square("e3")));
+        assert!(game.parse_move("d4e3").unwrap().capture.is_some());
+    }
+
+    #[test]
+    fn statistics() {
+        let stats = Game::default().statistics();
+        assert_eq!(stats.white_material, 4000);
+        assert_eq!(stats.black_material, 4000);
+        assert_eq!(stats.white_mobility, 20);
+        assert_eq!(stats.black_mobility, 20);
+        assert_eq!(stats.white_passed_pawns, 0);
+        assert_eq!(stats.white_doubled_pawns, 0);
+        assert!(!stats.in_check);
+
+        // Black is in check from the rook, with doubled and passed pawns on the b-file
+        let mut game = Game::new("4k3/1p6/1p6/8/8/8/8/3R2K1 w - - 0 1").unwrap();
+        let mov = game.parse_move("d1e1").unwrap();
+        game.make_move(mov);
+        let stats = game.statistics();
+        assert_eq!(stats.white_material, 500);
+        assert_eq!(stats.black_material, 200);
+        assert_eq!(stats.black_passed_pawns, 2);
+        assert_eq!(stats.black_doubled_pawns, 2);
+        assert!(stats.in_check);
+        assert_eq!(stats.ply, 1);
+    }
+
+    #[test]
+    fn score_position() {
+        // Fool's mate, White to move and mated
+        let game =
+            Game::new("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").unwrap();
+        assert_eq!(game.score_position(), -MATE_SCORE);
+
+        let stalemate = Game::new("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(stalemate.score_position(), 0);
+
+        // A pawn up, but the knights shuffle back and forth
+        let mut game = Game::new("4k1n1/8/8/8/8/8/P7/4K1N1 w - - 0 1").unwrap();
+        assert_ne!(game.score_position(), 0);
+        for mov in ["g1f3", "g8f6", "f3g1", "f6g8"].repeat(2) {
+            let mov = game.parse_move(mov).unwrap();
+            game.make_move(mov);
+        }
+        assert_eq!(game.score_position(), 0);
+    }
+
+    #[test]
+    fn current_fen_with_history() {
+        let mut game = Game::default();
+        for mov in ["e2e4", "c7c5", "g1f3"] {
+            let mov = game.parse_move(mov).unwrap();
+            game.make_move(mov);
+        }
+        let (fen, moves) = game.current_fen_with_history();
+        assert_eq!(fen, Game::STARTING_FEN);
+        assert_eq!(moves, ["e2e4", "c7c5", "g1f3"]);
+
+        let mut replayed = Game::new(&fen).unwrap();
+        for mov in &moves {
+            let mov = replayed.parse_move(mov).unwrap();
+            replayed.make_move(mov);
+        }
+        assert_eq!(replayed, game);
+    }
+
+    #[test]
+    fn all_fen_history() {
+        let mut game = Game::default();
+        let mut fens = vec![game.to_fen()];
+        for lan in [
+            "e2e4", "g8f6", "e4e5", "d7d5", "e5d6", "e7e6", "g1f3", "f8e7", "f1c4",
+        ] {
+            let mov = game.parse_move(lan).unwrap();
+            game.make_move(mov);
+            fens.push(game.to_fen());
+        }
+        for lan in ["e8g8", "e1g1"] {
+            let mov = game.parse_move(lan).unwrap();
+            game.make_move(mov);
+            fens.push(game.to_fen());
+        }
+        assert_eq!(game.all_fen_history(), fens);
+        assert_eq!(
+            fens[4],
+            "rnbqkb1r/ppp1pppp/5n2/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3"
+        );
+    }
+
+    #[test]
+    fn halfmove_clock() {
+        let mut game = Game::default();
+        let knights = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        for lan in knights.iter().cycle().take(102) {
+            let mov = game.parse_move(lan).unwrap();
+            game.make_move(mov);
+        }
+        assert_eq!(game.halfmove_clock, 102);
+        assert!(game.is_draw());
+
+        let push = game.parse_move("e2e4").unwrap();
+        game.make_move(push);
+        assert_eq!(game.halfmove_clock, 0);
+        game.unmake_move(push);
+        assert_eq!(game.halfmove_clock, 102);
+
+        let mut game = Game::new("4k3/8/8/3p4/8/8/8/3QK3 w - - 37 60").unwrap();
+        let quiet = game.parse_move("d1d2").unwrap();
+        game.make_move(quiet);
+        assert_eq!(game.halfmove_clock, 38);
+        game.unmake_move(quiet);
+        let capture = game.parse_move("d1d5").unwrap();
+        game.make_move(capture);
+        assert_eq!(game.halfmove_clock, 0);
+        assert!(!game.is_draw());
+    }
+
+    #[test]
+    fn checkmate_and_stalemate() {
+        // Back rank mate in one
+        let mut game = Game::new("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        assert!(!game.is_checkmate());
+        let mate = game.parse_move("a1a8").unwrap();
+        game.make_move(mate);
+        assert!(game.is_checkmate());
+        assert!(!game.is_stalemate());
+        // With luft on h7 the same check isn't mate
+        let mut game = Game::new("6k1/5pp1/7p/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let check = game.parse_move("a1a8").unwrap();
+        game.make_move(check);
+        assert!(game.board.in_check());
+        assert!(!game.is_checkmate());
+
+        // The black king has no square to go to, but isn't attacked
+        let mut game = Game::new("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert!(game.is_stalemate());
+        assert!(!game.is_checkmate());
+        assert!(!Game::default().is_stalemate());
+        // Checking leaves the game as it was
+        assert_eq!(game, Game::new("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap());
+    }
+
+    #[test]
+    fn fifty_move_rule() {
+        let mut game = Game::new("4k3/8/8/3p4/8/8/8/3QK3 w - - 99 80").unwrap();
+        assert!(!game.is_fifty_move_draw());
+        assert!(!game.is_draw());
+
+        let quiet = game.parse_move("d1d2").unwrap();
+        game.make_move(quiet);
+        assert_eq!(game.halfmove_clock, 100);
+        assert!(game.is_fifty_move_draw());
+        assert!(game.is_draw());
+        game.unmake_move(quiet);
+
+        // Taking the pawn on the last move before the limit starts the count over
+        let capture = game.parse_move("d1d5").unwrap();
+        game.make_move(capture);
+        assert_eq!(game.halfmove_clock, 0);
+        assert!(!game.is_fifty_move_draw());
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let mut game = Game::default();
+        let knights = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        // The starting position comes back after every fourth move, for the third time at the end
+        for (ply, lan) in knights.iter().cycle().take(8).enumerate() {
+            assert!(!game.is_repetition, "{ply}");
+            let mov = game.parse_move(lan).unwrap();
+            game.make_move(mov);
+        }
+        assert!(game.is_repetition);
+        assert!(game.is_draw());
+        assert!(game.history.is_repetition(Game::default().board.hash()));
+
+        let last = game.history.last_move().unwrap();
+        game.unmake_move(last);
+        assert!(!game.is_repetition);
+        game.make_move(last);
+        assert!(game.is_repetition);
+
+        // The rooks come back, but without their castling rights it's not the root position
+        let mut game = Game::new("r3k3/8/8/8/8/8/8/R3K3 w Qq - 0 1").unwrap();
+        let rooks = ["a1b1", "a8b8", "b1a1", "b8a8"];
+        for lan in rooks.iter().cycle().take(8) {
+            let mov = game.parse_move(lan).unwrap();
+            game.make_move(mov);
+        }
+        assert!(!game.is_repetition);
+        for lan in rooks {
+            let mov = game.parse_move(lan).unwrap();
+            game.make_move(mov);
+        }
+        assert!(game.is_repetition);
+    }
+
+    #[test]
+    fn fen_round_trip() {
+        for fen in [
+            Game::STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "r3k3/8/8/8/8/8/8/4K2R b Kq - 49 120",
+        ] {
+            let game = Game::new(fen).unwrap();
+            assert_eq!(game.to_fen(), fen);
+            assert_eq!(Game::new(&game.to_fen()).unwrap(), game);
+        }
+
+        // Positions reached by playing moves too, not just parsed ones
+        let mut game = Game::default();
+        for lan in ["e2e4", "d7d5", "e4e5", "f7f5"] {
+            let mov = game.parse_move(lan).unwrap();
+            game.make_move(mov);
+        }
+        let fen = game.to_fen();
+        assert_eq!(
+            fen,
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3"
+        );
+        assert_eq!(Game::new(&fen).unwrap().board, game.board);
+    }
+
+    #[test]
+    fn zobrist_hash() {
+        // Castling, en passant and promotions, with and without captures
+        for fen in [
+            Game::STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ] {
+            let mut game = Game::new(fen).unwrap();
+            let before = game.board.hash();
+            for mov in game.board.gen_legal_moves().unwrap() {
+                game.make_move(mov);
+                assert_eq!(
+                    game.board.hash(),
+                    game.board.zobrist_hash_from_scratch(),
+                    "{fen} {mov}"
+                );
+                assert_ne!(game.board.hash(), before, "{fen} {mov}");
+                game.unmake_move(mov);
+                assert_eq!(game.board.hash(), before, "{fen} {mov}");
+            }
+        }
+
+        // The same position reached through different move orders
+        let play = |moves: &[&str]| {
+            let mut game = Game::default();
+            for lan in moves {
+                let mov = game.parse_move(lan).unwrap();
+                game.make_move(mov);
+            }
+            game.board.hash()
+        };
+        assert_eq!(
+            play(&["g1f3", "b8c6", "b1c3"]),
+            play(&["b1c3", "b8c6", "g1f3"])
+        );
+        assert_eq!(
+            play(&["g1f3", "g8f6", "f3g1", "f6g8"]),
+            Game::default().board.hash()
+        );
+        // Same pieces, different side to move
+        let mut board = Game::default().board;
+        board.flip_turn();
+        assert_ne!(board.hash(), Game::default().board.hash());
+    }
+
+    #[test]
+    fn set_position() {
+        let mut game = Game::new(Game::STARTING_FEN).unwrap();
+        let mov = game.parse_move("e2e4").unwrap();
+        game.make_move(mov);
+
+        let fen = "4k3/8/8/8/8/8/8/4K2R b K - 12 40";
+        game.set_position(fen).unwrap();
+        assert_eq!(game, Game::new(fen).unwrap());
+        assert_eq!(game.to_fen(), fen);
+
+        assert!(game.set_position("4k3/8/8/8/8/8/8/4X3 w - - 0 1").is_err());
+        assert_eq!(game.to_fen(), fen);
+    }
+
+    #[test]
+    fn malformed_fen() {
+        for (bad, err) in [
+            (
+                "8/8/8/8 w -",
+                FenError::MissingFields("8/8/8/8 w -".to_string()),
+            ),
+            (
+                "k7/8/8/8/8/8/8/K7 w X - 0 1",
+                FenError::InvalidCastling("k7/8/8/8/8/8/8/K7 w X - 0 1".to_string(), 'X'),
+            ),
+            (
+                "k7/8/8/8/8/8/8/K7 w - - x 1",
+                FenError::InvalidMoveCounter("x".to_string()),
+            ),
+            (
+                "k7/8/8/8/8/8/8/K7 w - - 0 y",
+                FenError::InvalidMoveCounter("y".to_string()),
+            ),
+            // Nine files, and a ninth rank
+            (
+                "k7/8/8/8/8/8/8/K7p w - - 0 1",
+                FenError::InvalidFen("k7/8/8/8/8/8/8/K7p w - - 0 1".to_string(), 'p'),
+            ),
+            (
+                "k7/8/8/8/8/8/8/K7/8 w - - 0 1",
+                FenError::InvalidFen("k7/8/8/8/8/8/8/K7/8 w - - 0 1".to_string(), '/'),
+            ),
+        ] {
+            assert_eq!(Game::new(bad), Err(err), "{bad}");
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let mut game = Game::default();
+        let mut moves = vec![];
+        for lan in ["e2e4", "g8f6", "e4e5", "d7d5", "e5d6", "f6g8", "g1f3"] {
+            let mov = game.parse_move(lan).unwrap();
+            game.make_move(mov);
+            moves.push(mov);
+        }
+        let json = serde_json::to_string(&game).unwrap();
+        let mut restored: Game = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, game);
+        assert_eq!(restored.to_fen(), game.to_fen());
+        // The history comes along, repetition counts included, so the moves can be taken back
+        for mov in moves.into_iter().rev() {
+            restored.unmake_move(mov);
+        }
+        assert_eq!(restored, Game::default());
+    }
+
+    #[test]
+    fn parse_promotions() {
+        let game = Game::new("1r2k3/2P5/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        for (lan, kind) in [
+            ("c7c8n", Kind::Knight),
+            ("c7c8b", Kind::Bishop),
+            ("c7c8r", Kind::Rook),
+            ("c7b8q", Kind::Queen),
+        ] {
+            let mov = game.parse_move(lan).unwrap();
+            assert_eq!(mov.promotion, Some(kind), "{lan}");
+            assert_eq!(mov.lan_string(), lan);
+        }
+        assert!(game.parse_move("c7c8k").is_err());
+        assert!(game.parse_move("c7c8x").is_err());
+    }
+
+    #[test]
+    fn illegal_positions() {
+        let violations = |fen: &str| match Game::new(fen) {
+            Err(FenError::IllegalPosition(_, violations)) => violations,
+            other => panic!("{fen} gave {other:?}"),
+        };
+        assert_eq!(
+            violations("KK6/8/8/8/8/8/8/k7 w - - 0 1"),
+            ["White has more than one king"]
+        );
+        // No pawn behind the en passant square to take
+        assert_eq!(
+            violations("k7/8/8/3P4/8/8/8/7K w - e6 0 1"),
+            ["En passant square e6 doesn't follow a double pawn push"]
+        );
+        // Queenside castling without the rook on a1
+        assert_eq!(
+            violations("4k3/8/8/8/8/8/8/4K2R w KQ - 0 1"),
+            ["White queenside castling is allowed but the king or rook has moved"]
+        );
+        // White could take the king
+        assert_eq!(
+            violations("4k3/8/8/8/8/8/8/4RK2 w - - 0 1"),
+            ["Black is in check on White's turn"]
+        );
+    }
+
+    #[test]
+    fn pgn_movetext() {
+        let mut game = Game::new(Game::STARTING_FEN).unwrap();
+        for mov in ["e2e4", "e7e5", "d1h5", "b8c6", "f1c4", "g8f6", "h5f7"] {
+            let mov = game.parse_move(mov).unwrap();
+            game.make_move(mov);
+        }
+        assert_eq!(
+            game.movelist_to_pgn_movetext(),
+            "1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0"
+        );
+
+        // Starting with Black, and both rooks can reach d1
+        let mut game = Game::new("4k3/8/8/8/8/8/6K1/R6R b - - 0 30").unwrap();
+        for mov in ["e8d7", "a1d1"] {
+            let mov = game.parse_move(mov).unwrap();
+            game.make_move(mov);
+        }
+        assert_eq!(game.movelist_to_pgn_movetext(), "30... Kd7 31. Rad1+ *");
+    }
+}
@@ -1,18 +1,46 @@
-use crate::{bitboard::Bitboard, r#move::Move};
+use crate::{
+    bitboard::Bitboard,
+    board::CastlingRights,
+    piece::{Kind, Piece},
+    r#move::Move,
+    zobrist::ZobristMap,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HistoryItem {
     pub r#move: Move,
     pub squares_attacked: Bitboard,
+    // Halfmove clock before the move was made, so it can be restored after a reset
+    pub prior_halfmove_clock: u8,
+    // En passant target before the move was made. Unmaking a move can't work it out by itself
+    pub prior_en_passant: Option<Bitboard>,
+    // Castling rights can't be worked out from the move either
+    pub prior_castling: CastlingRights,
+    // Zobrist hash of the position the move led to
+    pub hash: u64,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct History(pub Vec<HistoryItem>);
+impl HistoryItem {
+    // Pawn moves and captures can't be undone over the board, so they reset the halfmove clock
+    pub fn resets_halfmove_clock(&self) -> bool {
+        self.r#move.what.kind == Kind::Pawn || self.r#move.capture.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct History {
+    items: Vec<HistoryItem>,
+    // Times each position has been on the board, the root position included. Positions that
+    // are no longer there are removed, so equal histories compare equal
+    occurrences: ZobristMap<u8>,
+}
 
 impl std::fmt::Display for History {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         // print all moves in algebraic notation
-        for item in &self.0 {
+        for item in &self.items {
             let _ = write!(f, "{} ", item.r#move);
         }
         Ok(())
@@ -20,10 +48,107 @@ impl std::fmt::Display for History {
 }
 
 impl History {
+    // Empty history for a game that starts from the position with hash `root_hash`
+    pub fn new(root_hash: u64) -> Self {
+        let mut history = Self::default();
+        history.reset(root_hash);
+        history
+    }
+
+    // Forgets every move, keeping the allocations around
+    pub fn reset(&mut self, root_hash: u64) {
+        self.items.clear();
+        self.occurrences.clear();
+        self.occurrences.insert(root_hash, 1);
+    }
+
     pub fn push(&mut self, item: HistoryItem) {
-        self.0.push(item);
+        *self.occurrences.entry(item.hash).or_insert(0) += 1;
+        self.items.push(item);
     }
     pub fn pop(&mut self) -> Option<HistoryItem> {
-        self.0.pop()
+        let item = self.items.pop()?;
+        if let Some(count) = self.occurrences.get_mut(&item.hash) {
+            *count -= 1;
+            if *count == 0 {
+                self.occurrences.remove(&item.hash);
+            }
+        }
+        Some(item)
+    }
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    pub fn iter(&self) -> std::slice::Iter<'_, HistoryItem> {
+        self.items.iter()
+    }
+
+    // The position with this hash has been on the board three times or more
+    pub fn is_repetition(&self, hash: u64) -> bool {
+        self.occurrences.get(&hash).is_some_and(|&count| count >= 3)
+    }
+
+    // Indices of every move that reset the halfmove clock, in ascending order. No position before
+    // one of these can repeat after it
+    pub fn fifty_move_reset_points(&self) -> Vec<usize> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| item.resets_halfmove_clock())
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    pub fn last_irreversible(&self) -> Option<usize> {
+        self.items
+            .iter()
+            .rposition(HistoryItem::resets_halfmove_clock)
+    }
+
+    pub fn last_move(&self) -> Option<Move> {
+        self.items.last().map(|item| item.r#move)
+    }
+
+    // The move before the last one, made by the side to move
+    pub fn second_to_last_move(&self) -> Option<Move> {
+        let idx = self.items.len().checked_sub(2)?;
+        self.items.get(idx).map(|item| item.r#move)
+    }
+
+    pub fn last_captured_piece(&self) -> Option<Piece> {
+        self.last_move().and_then(|mov| mov.capture)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Game;
+
+    fn play(lans: &[&str]) -> Game {
+        let mut game = Game::default();
+        for lan in lans {
+            let mov = game.parse_move(lan).unwrap();
+            game.make_move(mov);
+        }
+        game
+    }
+
+    #[test]
+    fn fifty_move_reset_points() {
+        // Pawn moves at 0 and 6, captures at 3, 5 and 6
+        let game = play(&[
+            "e2e4", "g8f6", "g1f3", "f6e4", "b1c3", "e4c3", "d2c3", "b8c6",
+        ]);
+        assert_eq!(game.history.fifty_move_reset_points(), [0, 3, 5, 6]);
+        assert_eq!(game.history.last_irreversible(), Some(6));
+
+        // Knight moves never reset the clock
+        let game = play(&["g1f3", "g8f6"]);
+        assert!(game.history.fifty_move_reset_points().is_empty());
+        assert_eq!(game.history.last_irreversible(), None);
+        assert_eq!(Game::default().history.last_irreversible(), None);
     }
 }
@@ -3,9 +3,14 @@ pub mod board;
 pub mod eval;
 pub mod game;
 pub mod history;
+pub mod magic;
 pub mod r#move;
 pub mod move_generation;
 pub mod perft;
 pub mod piece;
+pub mod search;
+pub mod tt;
+pub mod uci;
+pub mod zobrist;
 
 pub use game::Game;
@@ -0,0 +1,303 @@
+use std::sync::OnceLock;
+
+use crate::{bitboard::Bitboard, board::Board};
+
+// Slider attacks through magic bitboards. The blockers that matter for a square, multiplied by
+// that square's magic number, give a perfect index into a table of precomputed attacks
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Magic {
+    // Squares whose occupancy changes the attacks. The board edges never do
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    // Where this square's slice of `MagicTables::attacks` starts
+    offset: usize,
+}
+
+impl Magic {
+    #[inline(always)]
+    fn index(&self, occupancy: Bitboard) -> usize {
+        self.offset + ((occupancy.0 & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct MagicTables {
+    rook: [Magic; 64],
+    bishop: [Magic; 64],
+    attacks: Vec<Bitboard>,
+}
+
+static TABLES: OnceLock<MagicTables> = OnceLock::new();
+
+fn tables() -> &'static MagicTables {
+    TABLES.get_or_init(MagicTables::generate)
+}
+
+#[inline(always)]
+pub fn rook_attacks(square: usize, occupancy: Bitboard) -> Bitboard {
+    let tables = tables();
+    tables.attacks[tables.rook[square].index(occupancy)]
+}
+
+#[inline(always)]
+pub fn bishop_attacks(square: usize, occupancy: Bitboard) -> Bitboard {
+    let tables = tables();
+    tables.attacks[tables.bishop[square].index(occupancy)]
+}
+
+#[inline(always)]
+pub fn queen_attacks(square: usize, occupancy: Bitboard) -> Bitboard {
+    rook_attacks(square, occupancy) | bishop_attacks(square, occupancy)
+}
+
+fn rook_mask(square: Bitboard) -> u64 {
+    let rank =
+        Board::rook_attacks_on_rank(square, Bitboard(0)) & !(Bitboard::FILE_A | Bitboard::FILE_H);
+    let file =
+        Board::rook_attacks_on_file(square, Bitboard(0)) & !(Bitboard::RANK_1 | Bitboard::RANK_8);
+    (rank | file).0
+}
+
+fn bishop_mask(square: Bitboard) -> u64 {
+    let edges = Bitboard::FILE_A | Bitboard::FILE_H | Bitboard::RANK_1 | Bitboard::RANK_8;
+    (Board::bishop_attacks_hq(square, Bitboard(0)) & !edges).0
+}
+
+// Found with a search over sparse random numbers from a fixed seed, then kept so the tables only
+// need filling in at startup
+const ROOK_MAGICS: [u64; 64] = [
+    0x0980_0080_1140_0020,
+    0x8340_0044_1000_2000,
+    0x0880_2000_9000_8268,
+    0x0080_0800_8010_0004,
+    0x8100_1100_0402_0800,
+    0x0300_0100_0400_0822,
+    0x0880_1A00_2900_0080,
+    0x8100_0500_0120_4882,
+    0x0844_8000_8140_0320,
+    0x0804_4020_1000_4000,
+    0x0108_8020_0310_0480,
+    0x5C00_8080_1000_0800,
+    0x0003_0018_0100_1014,
+    0x0002_0002_0004_1008,
+    0x0004_0081_0804_2210,
+    0x0105_0001_0000_9042,
+    0x0400_8080_0040_0021,
+    0xC100_4040_1000_2000,
+    0x0060_0080_1000_2088,
+    0x0400_8080_0800_1000,
+    0x4440_8080_0800_0400,
+    0x1002_0080_0400_0280,
+    0x4002_4400_300D_1248,
+    0x0010_0200_0040_8104,
+    0x0101_0082_0020_4200,
+    0x8020_0020_4000_5000,
+    0x4100_1000_8080_2000,
+    0x4008_006A_8010_0280,
+    0x0021_0085_0010_0800,
+    0x5000_0400_8080_0200,
+    0x0010_0401_0100_0200,
+    0x6140_0042_0000_8104,
+    0x4000_4000_2080_0090,
+    0x2020_0020_8080_4000,
+    0x0000_4082_0200_2010,
+    0x0080_1005_0100_0820,
+    0x0004_0400_8080_0800,
+    0xA01A_8002_0080_0400,
+    0x0014_8201_0400_9008,
+    0x0008_0040_8200_0401,
+    0x0029_8040_0460_8010,
+    0x0140_0020_0081_8048,
+    0x000C_4104_2001_0014,
+    0x0020_1000_0800_8080,
+    0x8000_0800_0400_8080,
+    0x0082_0080_0400_8002,
+    0x1002_0008_0102_0004,
+    0x0010_8485_0562_0004,
+    0x0000_8018_4000_2080,
+    0x2002_010C_8040_3200,
+    0x9000_1142_2001_0300,
+    0x0001_0008_2010_0100,
+    0x000A_8004_0108_0080,
+    0xC001_4004_1020_0801,
+    0x4800_4801_0230_0400,
+    0x1000_0100_4084_0200,
+    0x0002_8004_4216_2101,
+    0x4000_8100_1020_4202,
+    0x0400_2012_0008_4082,
+    0x8200_2100_0408_1001,
+    0x1002_0010_0420_0802,
+    0x0005_0002_0804_0001,
+    0x0002_0027_01AC_0822,
+    0x0000_1025_0184_004A,
+];
+
+const BISHOP_MAGICS: [u64; 64] = [
+    0x4014_7002_0801_2480,
+    0x0220_0142_00A1_0001,
+    0x8108_0840_4080_6120,
+    0x200E_0A02_001A_00C0,
+    0x4442_0210_8024_3000,
+    0x4C00_9010_0810_0050,
+    0x0800_8211_1040_4840,
+    0x0000_1202_2202_4003,
+    0x008A_08A0_4102_0200,
+    0x0001_1846_0C04_28A0,
+    0x0000_314B_0602_0022,
+    0x0804_1804_8108_0025,
+    0x0000_3405_2000_4880,
+    0x0440_3090_0420_0008,
+    0x2108_0844_02A0_1000,
+    0x0328_0244_2208_2200,
+    0xC212_00C0_0431_0C00,
+    0x4608_4004_0820_C400,
+    0x0010_004A_0272_0020,
+    0x0008_0048_8A04_4044,
+    0x0025_000A_9040_000A,
+    0x0009_0106_0082_2104,
+    0x0021_0404_0082_1002,
+    0x0801_000A_1901_0120,
+    0x0004_0482_1091_5000,
+    0x0310_2220_0524_2C05,
+    0x0054_4800_101C_8010,
+    0x2308_0800_0082_0003,
+    0x2008_8200_0401_0401,
+    0x0650_1102_4420_8800,
+    0x0009_3244_0108_0802,
+    0x0100_8022_A601_0C40,
+    0x8081_2008_0420_0940,
+    0x300C_0403_0520_0240,
+    0x0002_0622_0024_0800,
+    0x2801_400A_0004_2200,
+    0x1409_0104_0002_02A0,
+    0x05B0_0200_8008_1048,
+    0x0A10_0109_1002_0880,
+    0xC403_0202_2206_0B00,
+    0x260D_3012_5040_2080,
+    0x0000_A808_0208_8800,
+    0x0001_0080_4102_3000,
+    0x0000_0202_0201_6420,
+    0x0008_5405_0214_0400,
+    0x0201_1010_0840_0B80,
+    0x0104_1042_0200_0058,
+    0x0002_0801_0100_0031,
+    0x4200_A201_2020_0004,
+    0x0004_2084_1009_0400,
+    0x10A0_1825_0808_8000,
+    0x1814_0814_2088_0800,
+    0x1008_8108_5034_0611,
+    0x0081_4822_4802_0610,
+    0x0009_0808_0094_0000,
+    0x0060_0102_4089_0800,
+    0x048A_9208_0208_0500,
+    0x2600_0E92_0490_0404,
+    0x2000_02A0_8048_2200,
+    0xAC80_1842_2020_A808,
+    0x929A_0004_40A5_0308,
+    0x4180_1040_826C_0110,
+    0x0140_C010_8101_0101,
+    0xC0A0_0122_0604_0EA0,
+];
+// Lays out the attacks for every relevant occupancy of `square` at the end of `attacks`.
+// Occupancies that share an index have to share the attacks too
+fn fill_table(
+    mask: u64,
+    magic: u64,
+    reference: fn(Bitboard, Bitboard) -> Bitboard,
+    square: Bitboard,
+    attacks: &mut Vec<Bitboard>,
+) -> Magic {
+    let bits = mask.count_ones();
+    let entry = Magic {
+        mask,
+        magic,
+        shift: 64 - bits,
+        offset: attacks.len(),
+    };
+    attacks.resize(entry.offset + (1 << bits), Bitboard(0));
+    // Every subset of the mask, through the carry-rippler trick
+    let mut subset = 0u64;
+    loop {
+        let attacked = reference(square, Bitboard(subset));
+        let slot = &mut attacks[entry.index(Bitboard(subset))];
+        debug_assert!(
+            slot.is_empty() || *slot == attacked,
+            "Magic {magic:#x} collides on {square}"
+        );
+        *slot = attacked;
+        subset = subset.wrapping_sub(mask) & mask;
+        if subset == 0 {
+            break;
+        }
+    }
+    entry
+}
+
+impl MagicTables {
+    fn generate() -> Self {
+        let mut attacks = Vec::new();
+        let mut rook = [Magic::default(); 64];
+        let mut bishop = [Magic::default(); 64];
+        for idx in 0..64 {
+            let square = Bitboard(1 << idx);
+            rook[idx] = fill_table(
+                rook_mask(square),
+                ROOK_MAGICS[idx],
+                Board::rook_attacks_hq,
+                square,
+                &mut attacks,
+            );
+            bishop[idx] = fill_table(
+                bishop_mask(square),
+                BISHOP_MAGICS[idx],
+                Board::bishop_attacks_hq,
+                square,
+                &mut attacks,
+            );
+        }
+        Self {
+            rook,
+            bishop,
+            attacks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn magic_attacks_match_hq() {
+        let mut seed: u64 = 0x0123_4567_89AB_CDEF;
+        for _ in 0..50 {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            for occupancy in [Bitboard(seed), Bitboard(seed & (seed >> 3)), Bitboard(0)] {
+                for idx in 0..64 {
+                    let square = Bitboard(1 << idx);
+                    assert_eq!(
+                        rook_attacks(idx, occupancy),
+                        Board::rook_attacks_hq(square, occupancy),
+                        "rook on {idx} with occupancy {occupancy}"
+                    );
+                    assert_eq!(
+                        bishop_attacks(idx, occupancy),
+                        Board::bishop_attacks_hq(square, occupancy),
+                        "bishop on {idx} with occupancy {occupancy}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn relevant_masks() {
+        // a1 and d4 for rooks, 12 and 10 squares; d4 for bishops, 9 squares
+        assert_eq!(rook_mask(Bitboard(1)).count_ones(), 12);
+        assert_eq!(rook_mask(Bitboard(1 << 27)).count_ones(), 10);
+        assert_eq!(bishop_mask(Bitboard(1 << 27)).count_ones(), 9);
+    }
+}
@@ -4,14 +4,27 @@
 use std::env;
 use std::error::Error;
 
+use rust_chess::bitboard::{display::BitboardDisplay, Bitboard};
 use rust_chess::perft::{perft, perft_parallel, test_parallelism};
+use rust_chess::uci::uci_loop;
 use rust_chess::Game;
 
 fn main() -> Result<(), Box<dyn Error>> {
     const DEFAULT_DEPTH: u8 = 4;
     color_eyre::install()?;
 
-    let perft_depth = if let Some(depth) = env::args().nth(1) {
+    // Talk UCI on stdin and stdout instead, for GUIs
+    if env::args().any(|arg| arg == "--uci") {
+        uci_loop();
+        return Ok(());
+    }
+
+    let fen_history = env::args().any(|arg| arg == "--fen-history");
+    #[cfg(feature = "debug-interactive")]
+    let flags: Vec<String> = env::args().filter(|arg| arg.starts_with("--")).collect();
+    let args: Vec<String> = env::args().filter(|arg| !arg.starts_with("--")).collect();
+
+    let perft_depth = if let Some(depth) = args.get(1) {
         let perft_depth = depth.parse::<u8>()?;
         // if we received depth 0, use default
         if perft_depth == 0 {
@@ -23,10 +36,11 @@ fn main() -> Result<(), Box<dyn Error>> {
         DEFAULT_DEPTH
     };
 
-    let fen = env::args()
-        .nth(2)
+    let fen = args
+        .get(2)
+        .cloned()
         .unwrap_or_else(|| Game::STARTING_FEN.to_string());
-    let moves: String = env::args().nth(3).unwrap_or_default();
+    let moves: String = args.get(3).cloned().unwrap_or_default();
     let mut game = Game::new(&fen)?;
 
     if !moves.is_empty() {
@@ -36,9 +50,31 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
-    // let n_moves = perft_parallel(&game, perft_depth, true);
-    let n_moves = perft(&mut game, perft_depth, true);
+    // Inspect the position instead of running perft
+    #[cfg(feature = "debug-interactive")]
+    for flag in &flags {
+        if let Some(square) = flag.strip_prefix("--attacks=") {
+            game.board.print_attacks(Bitboard::from_algebraic(square)?);
+            return Ok(());
+        }
+        if let Some(square) = flag.strip_prefix("--moves=") {
+            game.board
+                .print_legal_moves_for(Bitboard::from_algebraic(square)?);
+            return Ok(());
+        }
+    }
+
+    // Bulk counting at depth 1 would hide the per-move divide output
+    let bulk = perft_depth > 1;
+    // let n_moves = perft_parallel(&game, perft_depth, true, bulk);
+    let n_moves = perft(&mut game, perft_depth, true, bulk);
     // test_parallelism();
     println!("\n{n_moves}");
+
+    if fen_history {
+        for fen in game.all_fen_history() {
+            println!("{fen}");
+        }
+    }
     Ok(())
 }
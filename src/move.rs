@@ -5,11 +5,14 @@ use crate::{
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Move {
     pub what: Piece,
     pub from: Bitboard,
     pub to: Bitboard,
     pub capture: Option<Piece>, // To unmake move
+    // When set, `what` is always a pawn and this is the kind it becomes on `to`. Undoing the move
+    // turns it back into a pawn
     pub promotion: Option<Kind>,
     pub en_passant: Option<Bitboard>,
     pub castling_rights_change: CastlingRights, // Keep track of changes to castling rights
@@ -45,6 +48,13 @@ impl Move {
         }
     }
     const fn with_promotion(mut self, promotion: Kind) -> Self {
+        #[cfg(debug_assertions)]
+        {
+            assert!(
+                matches!(self.what.kind, Kind::Pawn) && promotion.is_promotable(),
+                "Only pawns can promote, and only to a knight, bishop, rook or queen"
+            );
+        }
         self.promotion = Some(promotion);
         self
     }
@@ -83,6 +93,80 @@ impl Move {
         self.capture = Some(capture);
         self
     }
+
+    pub const fn is_capture(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    pub const fn is_double_pawn_push(&self) -> bool {
+        self.en_passant.is_some()
+    }
+
+    // Square skipped by a double pawn push, where an enemy pawn could take en passant. En passant
+    // captures themselves are just captures and don't set it
+    pub const fn en_passant_target(&self) -> Option<Bitboard> {
+        self.en_passant
+    }
+
+    pub const fn is_promotion(&self) -> bool {
+        self.promotion.is_some()
+    }
+
+    // Kind of the piece standing on `to` once the move is made
+    pub fn promoted_kind(&self) -> Kind {
+        self.promotion.unwrap_or(self.what.kind)
+    }
+
+    // The piece that moved, going from `to` back to `from` as whatever it is on `to`. Captures,
+    // promotions and castling are left out: the result undoes the move, it can't be replayed
+    pub fn reverse(&self) -> Self {
+        Self::new(
+            self.to,
+            self.from,
+            self.what.with_kind(self.promoted_kind()).on_square(self.to),
+        )
+    }
+
+    // Long algebraic notation, as used by UCI (e2e4, e7e8q)
+    pub fn lan_string(&self) -> String {
+        let mut lan = self.to_string();
+        if let Some(promotion) = self.promotion {
+            lan.push(promotion.to_letter().to_ascii_lowercase());
+        }
+        lan
+    }
+
+    // Best-effort SAN (Nf3, exd5, O-O) without disambiguation or check markers, so it doesn't
+    // need the list of legal moves
+    pub fn san_preview(&self) -> String {
+        if self.castle_move.is_some() {
+            return if self.to.idx() > self.from.idx() {
+                "O-O".to_string()
+            } else {
+                "O-O-O".to_string()
+            };
+        }
+
+        let square = |square: Bitboard| square.to_algebraic().unwrap_or_else(|_| "EE".to_string());
+        let mut san = String::new();
+        match self.what.kind {
+            Kind::Pawn => {
+                if self.capture.is_some() {
+                    san.push_str(&square(self.from)[..1]);
+                }
+            }
+            kind => san.push(kind.to_letter()),
+        }
+        if self.capture.is_some() {
+            san.push('x');
+        }
+        san.push_str(&square(self.to));
+        if let Some(promotion) = self.promotion {
+            san.push('=');
+            san.push(promotion.to_letter());
+        }
+        san
+    }
 }
 
 impl std::fmt::Display for Move {
@@ -97,3 +181,58 @@ impl std::fmt::Display for Move {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::Game;
+
+    // `lan_string` and `san_preview` of each move, played one after the other from `fen`
+    fn notations(fen: &str, lans: &[&str]) -> Vec<(String, String)> {
+        let mut game = Game::new(fen).unwrap();
+        lans.iter()
+            .map(|lan| {
+                let mov = game.parse_move(lan).unwrap();
+                game.make_move(mov);
+                (mov.lan_string(), mov.san_preview())
+            })
+            .collect()
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected
+            .iter()
+            .map(|&(lan, san)| (lan.to_string(), san.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn promotions() {
+        let fen = "1r2k3/2P5/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(
+            notations(fen, &["c7c8n", "b8b7", "c8b6"]),
+            pairs(&[("c7c8n", "c8=N"), ("b8b7", "Rb7"), ("c8b6", "Nb6")])
+        );
+        assert_eq!(notations(fen, &["c7b8q"]), pairs(&[("c7b8q", "cxb8=Q")]));
+    }
+
+    #[test]
+    fn castling() {
+        assert_eq!(
+            notations("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", &["e1g1", "e8c8"]),
+            pairs(&[("e1g1", "O-O"), ("e8c8", "O-O-O")])
+        );
+    }
+
+    #[test]
+    fn no_disambiguation() {
+        // Both knights can reach d2, and the preview doesn't say which one went
+        let fen = "4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1";
+        assert_eq!(notations(fen, &["b1d2"]), pairs(&[("b1d2", "Nd2")]));
+        assert_eq!(notations(fen, &["f3d2"]), pairs(&[("f3d2", "Nd2")]));
+        // Pawn captures are named after the file they come from
+        assert_eq!(
+            notations(Game::STARTING_FEN, &["e2e4", "d7d5", "e4d5"]),
+            pairs(&[("e2e4", "e4"), ("d7d5", "d5"), ("e4d5", "exd5")])
+        );
+    }
+}
@@ -1,10 +1,11 @@
-use crate::{bitboard::BitboardError, r#move::Move};
+use crate::{bitboard::BitboardError, piece::Color, r#move::Move};
 use std::error::Error;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MovegenError {
     InvalidMove(String),
     BitboardError(BitboardError),
+    KingNotFound(Color),
 }
 
 impl From<Move> for MovegenError {
@@ -18,6 +19,7 @@ impl std::fmt::Display for MovegenError {
         match self {
             Self::InvalidMove(r#move) => write!(f, "Invalid move: {}", r#move),
             Self::BitboardError(err) => write!(f, "Bitboard error: {}", err),
+            Self::KingNotFound(color) => write!(f, "No {} king found on the board", color),
         }
     }
 }
@@ -1,6 +1,7 @@
 use crate::{
     bitboard::{display::BitboardDisplay, Bitboard, Direction},
     board::{Board, CastlingRights},
+    magic,
     piece::{Color, Kind, Piece},
     r#move::Move,
 };
@@ -19,6 +20,13 @@ pub trait Movegen {
         direction: Direction,
     );
     fn gen_moves(&self) -> Result<Vec<Move>, MovegenError>;
+    fn gen_legal_moves(&self) -> Result<Vec<Move>, MovegenError>;
+    fn gen_check_evasions(&self) -> Vec<Move>;
+    fn generate_all_captures(&self) -> Result<Vec<Move>, MovegenError>;
+    fn generate_promotions(&self) -> Vec<Move>;
+    fn gives_check(&self, mov: Move) -> bool;
+    fn generate_checks(&self) -> Vec<Move>;
+    fn generate_quiet_checks(&self) -> Vec<Move>;
     fn gen_sliding_moves(
         &self,
         moves: &mut Vec<Move>,
@@ -34,6 +42,12 @@ pub trait Movegen {
         color: Color,
     );
     fn gen_moves_from_piece(&self, origin_square: Bitboard) -> Vec<Move>;
+    fn gen_pawn_moves(&self, pawns: Bitboard) -> Vec<Move>;
+    fn generate_moves_from_square_bitboard(&self, squares: Bitboard) -> Vec<Move>;
+    fn generate_knight_moves_bulk(&self) -> Vec<Move>;
+    fn generate_king_moves_bulk(&self) -> Vec<Move>;
+    fn generate_recapture_moves(&self, target_square: Bitboard) -> Vec<Move>;
+    fn generate_slider_moves_bulk(&self) -> Vec<Move>;
     fn slide_until_blocked(
         &self,
         current_square: Bitboard,
@@ -41,7 +55,7 @@ pub trait Movegen {
         color: Color,
     ) -> Option<Piece>;
     fn is_attacked(&self, square: Bitboard, idx: usize, color: Color) -> bool;
-    fn is_check(&mut self, color: Color) -> bool;
+    fn is_check(&self, color: Color) -> Result<bool, MovegenError>;
 }
 
 impl Movegen for Board {
@@ -73,6 +87,8 @@ impl Movegen for Board {
         }
     }
 
+    // Same moves in the same order as `gen_sliding_moves_recursive`, one square per iteration
+    #[inline]
     fn gen_sliding_moves(
         &self,
         moves: &mut Vec<Move>,
@@ -80,7 +96,21 @@ impl Movegen for Board {
         origin_square: Bitboard,
         direction: Direction,
     ) {
-        self.gen_sliding_moves_recursive(moves, piece, origin_square, origin_square, direction);
+        let (color_mask, opposite_color_mask) = if piece.color == Color::White {
+            (self.white, self.black)
+        } else {
+            (self.black, self.white)
+        };
+        let mut current_square = origin_square.shift(direction);
+        while !current_square.is_empty() && !current_square.intersects(color_mask) {
+            let new_move = Move::new(origin_square, current_square, piece);
+            if current_square.intersects(opposite_color_mask) {
+                moves.push(new_move.with_capture(self.get_piece(current_square).unwrap()));
+                break;
+            }
+            moves.push(new_move);
+            current_square = current_square.shift(direction);
+        }
     }
 
     fn gen_castling_moves(
@@ -90,29 +120,24 @@ impl Movegen for Board {
         origin_square: Bitboard,
         color: Color,
     ) {
-        let (short_castling_rights, long_castling_rights, lost_rights) = match color {
-            Color::White => (
-                CastlingRights::WHITE_KINGSIDE,
-                CastlingRights::WHITE_QUEENSIDE,
-                CastlingRights::WHITE_BOTH,
-            ),
-            Color::Black => (
-                CastlingRights::BLACK_KINGSIDE,
-                CastlingRights::BLACK_QUEENSIDE,
-                CastlingRights::BLACK_BOTH,
-            ),
+        let lost_rights = match color {
+            Color::White => CastlingRights::WHITE_BOTH,
+            Color::Black => CastlingRights::BLACK_BOTH,
         };
+        // The king can't castle out of, through or into check. One attack map covers every
+        // square it could cross
+        let danger =
+            self.generate_king_danger_squares(color) & self.generate_castling_check_mask(color);
+        if origin_square.intersects(danger) {
+            return;
+        }
         // Short castle
-        if self.castling.get_castling_right(short_castling_rights) {
+        if self.can_castle_kingside(color) {
             let king_destination = origin_square.east().east();
             let rook_origin = king_destination.east();
             let rook_destination = origin_square.east();
 
-            // TODO: check if the king is in check during travel
-            if !rook_destination.intersects(self.anything())
-                && !king_destination.intersects(self.anything())
-                && !self.is_attacked(rook_destination, rook_destination.idx(), color)
-            {
+            if !(rook_destination | king_destination).intersects(self.anything() | danger) {
                 let mov = Move::new(origin_square, king_destination, piece)
                     .with_castling_rights_loss(lost_rights)
                     .with_castle_move((rook_origin, rook_destination));
@@ -120,7 +145,7 @@ impl Movegen for Board {
             }
         }
         // Long castle
-        if self.castling.get_castling_right(long_castling_rights) {
+        if self.can_castle_queenside(color) {
             let relevant_squares = match color {
                 Color::White => [
                     Bitboard::from_algebraic("a1").unwrap(),
@@ -142,12 +167,9 @@ impl Movegen for Board {
             let any_square_full = (travel_squares[0] | travel_squares[1] | travel_squares[2])
                 .intersects(self.anything());
 
-            let any_square_attacked = safe_squares
-                .iter()
-                .filter(|square| self.is_attacked(**square, square.idx(), color))
-                .collect::<Vec<&Bitboard>>();
+            let any_square_attacked = (safe_squares[0] | safe_squares[1]).intersects(danger);
 
-            if any_square_attacked.is_empty() && !any_square_full {
+            if !any_square_attacked && !any_square_full {
                 let mov = Move::new(origin_square, travel_squares[1], piece)
                     .with_castling_rights_loss(lost_rights)
                     .with_castle_move((relevant_squares[0], relevant_squares[3]));
@@ -156,8 +178,7 @@ impl Movegen for Board {
         }
     }
 
-    // pseudo-legal moves
-    // Does not check for check or pinned pieces
+    // pseudo-legal moves, except that they answer a check and pinned pieces stay on their pin ray
     fn gen_moves_from_piece(&self, origin_square: Bitboard) -> Vec<Move> {
         let Some(piece) = self.get_piece(origin_square) else {
             return vec![];
@@ -168,77 +189,7 @@ impl Movegen for Board {
             (self.black, self.white)
         };
         let moves: Vec<Move> = match piece.kind {
-            Kind::Pawn => {
-                let mut moves: Vec<Move> = vec![];
-                let to: Bitboard = if self.turn == Color::White {
-                    origin_square.north()
-                } else {
-                    origin_square.south()
-                };
-                let colors_mask = self.white | self.black;
-                if !to.is_empty() && !to.intersects(colors_mask) {
-                    // is promotion?
-                    let new_move = Move::new(origin_square, to, piece);
-                    if to.intersects(Bitboard::PAWN_PROMOTION_MASK) {
-                        moves.append(&mut new_move.with_promotions());
-                    } else {
-                        moves.push(new_move);
-                    }
-
-                    if origin_square.pawn_initial(current_turn_mask) {
-                        let new_to = if self.turn == Color::White {
-                            origin_square.north().north()
-                        } else {
-                            origin_square.south().south()
-                        };
-
-                        if !new_to.is_empty() && !new_to.intersects(colors_mask) {
-                            let mov = Move::new(origin_square, new_to, piece).with_en_passant(to);
-                            // println!("Move vulnerable to en passant: {} {}", mov, to.to_algebraic().unwrap());
-                            moves.push(mov);
-                        }
-                    }
-                }
-                // captures
-                // println!(
-                //     "Generating pawn captures for {} at {}",
-                //     piece,
-                //     origin_square.to_algebraic().unwrap()
-                // );
-                // if let Some(en_passant_square) = self.board.en_passant {
-                //     println!(
-                //         "Current en passant: {}",
-                //         en_passant_square.to_algebraic().unwrap()
-                //     );
-                // }
-                for to in Direction::pawn_captures(self.turn) {
-                    let to = origin_square.shift(to);
-                    if to.is_empty() {
-                        continue;
-                    }
-
-                    // Regular capture
-                    if to.intersects(opposite_color_mask) {
-                        let new_move = Move::new(origin_square, to, piece)
-                            .with_capture(self.get_piece(to).unwrap());
-                        if to.intersects(Bitboard::PAWN_PROMOTION_MASK) {
-                            moves.append(&mut new_move.with_promotions());
-                        } else {
-                            moves.push(new_move);
-                        }
-                    } else if let Some(en_passant_square) = self.en_passant {
-                        if to == en_passant_square {
-                            let victim_pawn =
-                                self.get_en_passant_victim(en_passant_square, !self.turn);
-
-                            let new_move =
-                                Move::new(origin_square, to, piece).with_capture(victim_pawn);
-                            moves.push(new_move);
-                        }
-                    }
-                }
-                moves
-            }
+            Kind::Pawn => self.gen_pawn_moves(origin_square),
             Kind::Knight => {
                 let mut moves: Vec<Move> = vec![];
 
@@ -255,58 +206,240 @@ impl Movegen for Board {
 
                 moves
             }
-            Kind::Bishop => {
-                let mut moves: Vec<Move> = vec![];
-                for direction in Direction::DIAGONAL_MOVES {
-                    self.gen_sliding_moves(&mut moves, piece, origin_square, direction);
-                }
-                moves
+            Kind::Bishop | Kind::Rook | Kind::Queen => {
+                let attacks =
+                    Self::sliding_attacks_from(origin_square, piece.kind, self.anything());
+                let lost_rights = CastlingRights::rights_for_rook_square(origin_square);
+                (attacks & !current_turn_mask)
+                    .map(|to| {
+                        let mut new_move = Move::new(origin_square, to, piece);
+                        if piece.kind == Kind::Rook {
+                            new_move = new_move.with_castling_rights_loss(lost_rights);
+                        }
+                        if to.intersects(opposite_color_mask) {
+                            new_move = new_move.with_capture(self.get_piece(to).unwrap());
+                        }
+                        new_move
+                    })
+                    .collect()
             }
-            Kind::Rook => {
-                let mut moves: Vec<Move> = vec![];
-                for direction in Direction::STRAIGHT_MOVES {
-                    self.gen_sliding_moves(&mut moves, piece, origin_square, direction);
+            Kind::King => self.generate_king_moves_bulk(),
+        };
+        // In check, anything but the king has to block or take the checker. En passant lands
+        // behind the pawn it takes, so look at the captured piece too
+        let checkmask = self.checkmask(piece.color);
+        if piece.kind == Kind::King {
+            return moves;
+        }
+        let pin_ray = self.pin_ray(piece.position.idx());
+        moves
+            .into_iter()
+            .filter(|mov| mov.to.intersects(pin_ray))
+            .filter(|mov| {
+                mov.to.intersects(checkmask)
+                    || mov
+                        .capture
+                        .is_some_and(|capture| capture.position.intersects(checkmask))
+            })
+            .collect()
+    }
+
+    // `gen_moves_from_piece` for every set square, empty ones included
+    fn generate_moves_from_square_bitboard(&self, squares: Bitboard) -> Vec<Move> {
+        let mut moves: Vec<Move> = vec![];
+        for square in squares {
+            moves.append(&mut self.gen_moves_from_piece(square));
+        }
+        moves
+    }
+
+    // Pseudo-legal moves for a set of pawns of the side to move, shifting the whole set at once
+    // and only walking the squares they land on
+    fn gen_pawn_moves(&self, pawns: Bitboard) -> Vec<Move> {
+        let mut moves: Vec<Move> = vec![];
+        let color = self.turn;
+        let pawns = pawns & self.get_pieces(Kind::Pawn, color);
+        let checkmask = self.checkmask(color);
+        let enemy = self.get_color_mask(!color);
+        let pawn = |from: Bitboard| Piece::new(color, Kind::Pawn, from);
+        let backwards = |to: Bitboard| match color {
+            Color::White => to.south(),
+            Color::Black => to.north(),
+        };
+        let forwards = |from: Bitboard| match color {
+            Color::White => from.north(),
+            Color::Black => from.south(),
+        };
+        let mut push = |mov: Move| {
+            if mov.to.intersects(Bitboard::PAWN_PROMOTION_MASK) {
+                moves.append(&mut mov.with_promotions());
+            } else {
+                moves.push(mov);
+            }
+        };
+
+        let single_pushes = forwards(pawns) & self.generate_pawn_push_targets(color) & checkmask;
+        for to in single_pushes {
+            push(Move::new(backwards(to), to, pawn(backwards(to))));
+        }
+
+        let double_pushes =
+            forwards(forwards(pawns)) & self.generate_pawn_double_push_targets(color) & checkmask;
+        for to in double_pushes {
+            let skipped = backwards(to);
+            let from = backwards(skipped);
+            push(Move::new(from, to, pawn(from)).with_en_passant(skipped));
+        }
+
+        // Each capture direction along with the way back to the pawn that made it
+        let captures = match color {
+            Color::White => [
+                (Direction::NorthEast, Direction::SouthWest),
+                (Direction::NorthWest, Direction::SouthEast),
+            ],
+            Color::Black => [
+                (Direction::SouthEast, Direction::NorthWest),
+                (Direction::SouthWest, Direction::NorthEast),
+            ],
+        };
+        for (direction, back) in captures {
+            for to in pawns.shift(direction) & enemy & checkmask {
+                let from = to.shift(back);
+                push(Move::new(from, to, pawn(from)).with_capture(self.get_piece(to).unwrap()));
+            }
+        }
+
+        if let Some(en_passant_square) = self.en_passant {
+            // The lookup for our color holds the attacks of an enemy pawn, the squares our pawns
+            // would take from
+            let attackers = self.pawn_attacks_lookup.get(color)[en_passant_square.idx()] & pawns;
+            if !attackers.is_empty() {
+                let victim_pawn = self.get_en_passant_victim(en_passant_square, !color);
+                if (en_passant_square | victim_pawn.position).intersects(checkmask) {
+                    for from in attackers {
+                        push(
+                            Move::new(from, en_passant_square, pawn(from))
+                                .with_capture(victim_pawn),
+                        );
+                    }
                 }
-                moves
             }
-            Kind::Queen => {
-                let mut moves: Vec<Move> = vec![];
-                for direction in Direction::SLIDING_MOVES {
-                    self.gen_sliding_moves(&mut moves, piece, origin_square, direction);
+        }
+        moves
+    }
+
+    // Pseudo-legal moves for every knight of the side to move. Shifts all of them at once in each
+    // of the eight directions, and shifts back to find the knight that landed on each square
+    fn generate_knight_moves_bulk(&self) -> Vec<Move> {
+        let mut moves: Vec<Move> = vec![];
+        let color = self.turn;
+        let knights = self.get_pieces(Kind::Knight, color);
+        let destinations = !self.get_color_mask(color) & self.checkmask(color);
+        let enemy = self.get_color_mask(!color);
+        for direction in Direction::KNIGHT_MOVES {
+            for to in knights.shift(direction) & destinations {
+                let from = to.shift(direction.opposite());
+                let mut new_move = Move::new(from, to, Piece::new(color, Kind::Knight, from));
+                if to.intersects(enemy) {
+                    new_move = new_move.with_capture(self.get_piece(to).unwrap());
                 }
-                moves
+                moves.push(new_move);
             }
-            Kind::King => {
-                let mut moves: Vec<Move> = vec![];
-                let lost_rights = match piece.color {
-                    Color::White => CastlingRights::WHITE_BOTH,
-                    Color::Black => CastlingRights::BLACK_BOTH,
-                };
-                for direction in Direction::SLIDING_MOVES {
-                    let to = origin_square.shift(direction);
-                    if !to.is_empty() && !to.intersects(current_turn_mask) {
-                        let mut new_move = Move::new(origin_square, to, piece)
-                            .with_castling_rights_loss(lost_rights);
-                        if to.intersects(opposite_color_mask) {
-                            new_move = new_move.with_capture(self.get_piece(to).unwrap());
-                        }
-                        moves.push(new_move);
-                    }
+        }
+        moves
+    }
+
+    // Pseudo-legal moves for the king of the side to move, castling included
+    fn generate_king_moves_bulk(&self) -> Vec<Move> {
+        let mut moves: Vec<Move> = vec![];
+        let color = self.turn;
+        let Ok(king_idx) = self.king_position(color) else {
+            return moves;
+        };
+        let origin_square = Bitboard(1 << king_idx);
+        let piece = Piece::new(color, Kind::King, origin_square);
+        let lost_rights = match color {
+            Color::White => CastlingRights::WHITE_BOTH,
+            Color::Black => CastlingRights::BLACK_BOTH,
+        };
+        let enemy = self.get_color_mask(!color);
+        let safe = !self.generate_king_danger_squares(color);
+        for to in self.king_attacks_lookup[king_idx] & !self.get_color_mask(color) & safe {
+            let mut new_move =
+                Move::new(origin_square, to, piece).with_castling_rights_loss(lost_rights);
+            if to.intersects(enemy) {
+                new_move = new_move.with_capture(self.get_piece(to).unwrap());
+            }
+            moves.push(new_move);
+        }
+        if origin_square.intersects(Bitboard::KING_INITIAL) {
+            self.gen_castling_moves(&mut moves, piece, origin_square, color);
+        }
+        moves
+    }
+
+    // Pseudo-legal moves for every rook, bishop and queen of the side to move. Each direction
+    // fills all the sliders that move that way at once, and the piece that reached a square is
+    // the first one found looking back the other way
+    fn generate_slider_moves_bulk(&self) -> Vec<Move> {
+        let mut moves: Vec<Move> = vec![];
+        let color = self.turn;
+        let own = self.get_color_mask(color);
+        let enemy = self.get_color_mask(!color);
+        let occupancy = self.anything();
+        let destinations = !own & self.checkmask(color);
+        let sliders = (self.rooks | self.bishops | self.queens) & own;
+
+        for from in sliders {
+            let piece = self.get_piece(from).unwrap();
+            let attacks = Self::sliding_attacks_from(from, piece.kind, occupancy);
+            for to in attacks & destinations {
+                let mut new_move = Move::new(from, to, piece);
+                if piece.kind == Kind::Rook {
+                    new_move = new_move
+                        .with_castling_rights_loss(CastlingRights::rights_for_rook_square(from));
                 }
-                // castling
-                if origin_square.intersects(Bitboard::KING_INITIAL) {
-                    match piece.color {
-                        Color::White => {
-                            self.gen_castling_moves(&mut moves, piece, origin_square, Color::White)
-                        }
-                        Color::Black => {
-                            self.gen_castling_moves(&mut moves, piece, origin_square, Color::Black)
-                        }
-                    }
+                if to.intersects(enemy) {
+                    new_move = new_move.with_capture(self.get_piece(to).unwrap());
                 }
-                moves
+                moves.push(new_move);
             }
+        }
+        moves
+    }
+
+    // Pseudo-legal captures on `target_square` by the side to move, found by looking at what
+    // attacks the square rather than generating every move
+    fn generate_recapture_moves(&self, target_square: Bitboard) -> Vec<Move> {
+        let mut moves: Vec<Move> = vec![];
+        let color = self.turn;
+        let Some(victim) = self
+            .get_piece(target_square)
+            .filter(|piece| piece.color != color)
+        else {
+            return moves;
         };
+        let mut attackers = self.attacks_to(target_square) & self.get_color_mask(color);
+        if !target_square.intersects(self.checkmask(color)) {
+            attackers &= self.kings;
+        }
+        for from in attackers {
+            let piece = self.get_piece(from).unwrap();
+            let mov = Move::new(from, target_square, piece).with_capture(victim);
+            match piece.kind {
+                Kind::Pawn if target_square.intersects(Bitboard::PAWN_PROMOTION_MASK) => {
+                    moves.append(&mut mov.with_promotions());
+                }
+                Kind::King => moves.push(mov.with_castling_rights_loss(match color {
+                    Color::White => CastlingRights::WHITE_BOTH,
+                    Color::Black => CastlingRights::BLACK_BOTH,
+                })),
+                Kind::Rook => moves.push(
+                    mov.with_castling_rights_loss(CastlingRights::rights_for_rook_square(from)),
+                ),
+                _ => moves.push(mov),
+            }
+        }
         moves
     }
 
@@ -346,97 +479,428 @@ impl Movegen for Board {
         // } else {
         //     (self.board.black, self.board.white)
         // };
-        let opposite_color_mask = self.get_color_mask(!color);
-        if (self.pawn_attacks_lookup.get(!color)[idx] // get the other color lookup
-            & self.pawns
-            & opposite_color_mask)
-            != Bitboard(0)
-        {
-            // eprintln!("{} Pawn check!\n{}", !self.turn, self);
+        if self.attacked_by_pawn(square, color) {
             return true;
         }
-        // println!("Side checked: {}", color);
-        // println!("Piece position: {}", idx);
-        // println!("Piece: {}", square);
-        // println!("Knight attacks: {}", self.knight_attacks_lookup[idx]);
-        // println!("Knights: {}", self.knights & opposite_color_mask);
-        if (self.knight_attacks_lookup[idx] & (self.knights & opposite_color_mask)) != Bitboard(0) {
-            // eprintln!("{} Knight check!\n{}", !self.turn, self);
-            // // print all previous moves
+        if (self.knight_attacks_lookup[idx] & self.get_pieces(Kind::Knight, !color)) != Bitboard(0)
+        {
             return true;
         }
 
-        // TODO: Use magic bitboards and pre-computed lookup tables for sliding pieces
-        for direction in [
-            Direction::North,
-            Direction::South,
-            Direction::East,
-            Direction::West,
-        ] {
-            // self.gen_sliding_moves(&mut moves, piece, origin_square, &direction);
-            let piece = self.slide_until_blocked(square, direction, color);
-            if let Some(piece) = piece {
+        let occupancy = self.anything();
+        let enemy = self.get_color_mask(!color);
+        let straight = (self.rooks | self.queens) & enemy;
+        let diagonal = (self.bishops | self.queens) & enemy;
+        magic::rook_attacks(idx, occupancy).intersects(straight)
+            || magic::bishop_attacks(idx, occupancy).intersects(diagonal)
+    }
+
+    fn is_check(&self, color: Color) -> Result<bool, MovegenError> {
+        let king_position = self.king_position(color)?;
+        let square = Bitboard(1 << king_position);
+        #[cfg(debug_assertions)]
+        {
+            assert!(square.count() == 1);
+        }
+        Ok(self.is_attacked(square, king_position, color))
+    }
+
+    fn gen_moves(&self) -> Result<Vec<Move>, MovegenError> {
+        let mut moves: Vec<Move> = vec![];
+
+        // Refuse to generate moves for a side without a king
+        self.king_position(self.turn)?;
+        if self.in_check() {
+            return Ok(self.gen_check_evasions());
+        }
+
+        moves.append(&mut self.gen_pawn_moves(self.pawns));
+        moves.append(&mut self.generate_knight_moves_bulk());
+        moves.append(&mut self.generate_king_moves_bulk());
+        moves.append(&mut self.generate_slider_moves_bulk());
+
+        let pins = self.pin_rays(self.turn);
+        moves.retain(|mov| respects_pins(mov, &pins));
+        Ok(moves)
+    }
+
+    // Moves for the side to move when its king is attacked. Against two checkers only the king
+    // can move, against one the other pieces can also take the checker or step in between
+    fn gen_check_evasions(&self) -> Vec<Move> {
+        let color = self.turn;
+        let mut moves = self.generate_king_moves_bulk();
+        let checkers = self.checkers(color);
+        if checkers.count() > 1 {
+            return moves;
+        }
+        let king = self.get_pieces(Kind::King, color);
+        let targets = Bitboard::between(king, checkers) | checkers;
+        let mut others = self.gen_pawn_moves(self.pawns);
+        others.append(&mut self.generate_knight_moves_bulk());
+        others.append(&mut self.generate_slider_moves_bulk());
+        // En passant takes the checking pawn without landing on it
+        let pins = self.pin_rays(color);
+        others.retain(|mov| {
+            (mov.to.intersects(targets)
+                || mov
+                    .capture
+                    .is_some_and(|capture| capture.position.intersects(checkers)))
+                && respects_pins(mov, &pins)
+        });
+        moves.append(&mut others);
+        moves
+    }
+
+    // Pseudo-legal moves that don't leave the mover's king in check
+    fn gen_legal_moves(&self) -> Result<Vec<Move>, MovegenError> {
+        let moves = self.gen_moves()?;
+        // In check, anything but the king has to capture the checker or block the check. Cheaper
+        // to rule out here than by playing the move
+        let checkmask = self.checkmask(self.turn);
+        Ok(moves
+            .into_iter()
+            .filter(|mov| {
+                mov.what.kind == Kind::King
+                    || mov.to.intersects(checkmask)
+                    || mov
+                        .capture
+                        .is_some_and(|capture| capture.position.intersects(checkmask))
+            })
+            .filter(|&mov| {
+                let mut after = *self;
+                after.move_piece(mov);
+                !after.is_check(self.turn).unwrap_or(true)
+            })
+            .collect())
+    }
+
+    // pseudo-legal captures only, including en passant and capture-promotions
+    fn generate_all_captures(&self) -> Result<Vec<Move>, MovegenError> {
+        let mut moves: Vec<Move> = vec![];
+
+        self.king_position(self.turn)?;
+
+        let opposite_color_mask = self.get_color_mask(!self.turn);
+        for origin_square in self.get_color_mask(self.turn) {
+            let Some(piece) = self.get_piece(origin_square) else {
+                continue;
+            };
+            // Sliders stop at the first piece in each direction, so there are no quiet squares
+            // to walk through
+            let attacks = self.generate_attacks_for_piece(piece);
+            for to in attacks & opposite_color_mask {
+                let mut new_move =
+                    Move::new(origin_square, to, piece).with_capture(self.get_piece(to).unwrap());
                 match piece.kind {
-                    Kind::Queen | Kind::Rook => {
-                        // eprintln!("{} Rook or queen check!\n{}", !self.turn, self);
-                        return true;
+                    Kind::Pawn if to.intersects(Bitboard::PAWN_PROMOTION_MASK) => {
+                        moves.append(&mut new_move.with_promotions());
+                        continue;
+                    }
+                    Kind::King => {
+                        new_move = new_move.with_castling_rights_loss(match piece.color {
+                            Color::White => CastlingRights::WHITE_BOTH,
+                            Color::Black => CastlingRights::BLACK_BOTH,
+                        });
+                    }
+                    Kind::Rook => {
+                        new_move = new_move.with_castling_rights_loss(
+                            CastlingRights::rights_for_rook_square(origin_square),
+                        );
                     }
                     _ => {}
                 }
+                moves.push(new_move);
             }
-        }
-        for direction in [
-            Direction::NorthEast,
-            Direction::NorthWest,
-            Direction::SouthEast,
-            Direction::SouthWest,
-        ] {
-            let piece = self.slide_until_blocked(square, direction, color);
-            if let Some(piece) = piece {
-                match piece.kind {
-                    Kind::Queen | Kind::Bishop => {
-                        // eprintln!("{}, Rook or bishop check!\n{}", !self.turn, self);
-                        return true;
+
+            if piece.kind == Kind::Pawn {
+                if let Some(en_passant_square) = self.en_passant {
+                    if attacks.intersects(en_passant_square) {
+                        let victim_pawn = self.get_en_passant_victim(en_passant_square, !self.turn);
+                        moves.push(
+                            Move::new(origin_square, en_passant_square, piece)
+                                .with_capture(victim_pawn),
+                        );
                     }
-                    _ => {}
                 }
             }
         }
-        false
+
+        Ok(moves)
     }
 
-    fn is_check(&mut self, color: Color) -> bool {
-        let king_position = self.king_position(color);
-        let square = Bitboard(1 << king_position);
-        #[cfg(debug_assertions)]
+    // Capture-promotions to any piece plus quiet promotions to a queen, the promotions worth
+    // looking at in quiescence search
+    fn generate_promotions(&self) -> Vec<Move> {
+        let about_to_promote = match self.turn {
+            Color::White => Bitboard::RANK_7,
+            Color::Black => Bitboard::RANK_2,
+        };
+        self.gen_pawn_moves(self.pawns & about_to_promote)
+            .into_iter()
+            .filter(|mov| mov.is_capture() || mov.promotion == Some(Kind::Queen))
+            .collect()
+    }
+
+    // Whether the move attacks the enemy king, either directly or by uncovering one of our
+    // sliders. Works on the current position, without making the move
+    fn gives_check(&self, mov: Move) -> bool {
+        let color = mov.what.color;
+        let Ok(king_idx) = self.king_position(!color) else {
+            return false;
+        };
+        let king = Bitboard(1 << king_idx);
+
+        // Occupancy and our own pieces as they will be after the move
+        let mut occupancy = (self.anything() & !mov.from) | mov.to;
+        if let Some(capture) = mov.capture {
+            occupancy &= !capture.position;
+        }
+        let mut unmoved = self.get_color_mask(color) & !mov.from;
+
+        if let Some((rook_origin, rook_destination)) = mov.castle_move {
+            occupancy = (occupancy & !rook_origin) | rook_destination;
+            unmoved &= !rook_origin;
+            let rook = mov.what.with_kind(Kind::Rook).on_square(rook_destination);
+            if self
+                .attacks_with_occupancy(rook, occupancy)
+                .intersects(king)
+            {
+                return true;
+            }
+        }
+
+        // Direct check
+        let moved = mov.what.with_kind(mov.promoted_kind()).on_square(mov.to);
+        if self
+            .attacks_with_occupancy(moved, occupancy)
+            .intersects(king)
         {
-            assert!(square.count() == 1);
+            return true;
         }
-        self.is_attacked(square, king_position, color)
+
+        // Discovered check, looking from the king towards our sliders
+        let diagonal_sliders = (self.bishops | self.queens) & unmoved;
+        let straight_sliders = (self.rooks | self.queens) & unmoved;
+        Self::ray_attacks(king, &Direction::DIAGONAL_MOVES, occupancy).intersects(diagonal_sliders)
+            || Self::ray_attacks(king, &Direction::STRAIGHT_MOVES, occupancy)
+                .intersects(straight_sliders)
     }
 
-    fn gen_moves(&self) -> Result<Vec<Move>, MovegenError> {
-        let mut moves: Vec<Move> = vec![];
+    // pseudo-legal moves that put the opponent in check
+    fn generate_checks(&self) -> Vec<Move> {
+        self.gen_moves()
+            .map(|moves| {
+                moves
+                    .into_iter()
+                    .filter(|mov| self.gives_check(*mov))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 
-        let current_turn_mask = if self.turn == Color::White {
-            self.white
-        } else {
-            self.black
-        };
-        for i in 0..64 {
-            let square = Bitboard(1 << i);
-
-            if square.intersects(current_turn_mask) {
-                #[cfg(debug_assertions)]
-                {
-                    self.get_piece(square)
-                        .map_or_else(|| panic!("No piece found at square: {i}"), |piece| piece);
+    // Checks that don't capture anything, direct or discovered. `gives_check` works both out
+    // from the attack tables without making the move
+    fn generate_quiet_checks(&self) -> Vec<Move> {
+        let mut checks = self.generate_checks();
+        checks.retain(|mov| !mov.is_capture());
+        checks
+    }
+}
+
+// A pinned piece can only move along the ray between its king and the pinning slider
+fn respects_pins(mov: &Move, pins: &[(Bitboard, Bitboard); 8]) -> bool {
+    pins.iter()
+        .all(|&(pinned, allowed)| !mov.from.intersects(pinned) || mov.to.intersects(allowed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Game;
+
+    #[test]
+    fn bulk_generation_matches_per_piece() {
+        for fen in [
+            Game::STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ] {
+            let board = Game::new(fen).unwrap().board;
+            let sorted = |moves: Vec<Move>| {
+                let mut moves = moves.iter().map(Move::lan_string).collect::<Vec<String>>();
+                moves.sort();
+                moves
+            };
+            let per_piece =
+                board.generate_moves_from_square_bitboard(board.get_color_mask(board.turn));
+            assert_eq!(
+                sorted(per_piece),
+                sorted(board.gen_moves().unwrap()),
+                "{fen}"
+            );
+        }
+    }
+
+    #[test]
+    fn iterative_sliding_moves_match_recursive() {
+        for fen in [
+            Game::STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        ] {
+            let board = Game::new(fen).unwrap().board;
+            for piece in board.piece_list() {
+                // Every piece slides, to cover pieces next to the edges and to their own men
+                for direction in Direction::SLIDING_MOVES {
+                    let mut iterative = vec![];
+                    let mut recursive = vec![];
+                    board.gen_sliding_moves(&mut iterative, piece, piece.position, direction);
+                    board.gen_sliding_moves_recursive(
+                        &mut recursive,
+                        piece,
+                        piece.position,
+                        piece.position,
+                        direction,
+                    );
+                    assert_eq!(iterative, recursive, "{fen} {piece} {direction:?}");
                 }
-                let mut piece_moves = self.gen_moves_from_piece(square);
-                moves.append(&mut piece_moves);
             }
         }
+    }
+
+    #[test]
+    fn check_evasions() {
+        let sorted = |moves: Vec<Move>| {
+            let mut moves = moves.iter().map(Move::lan_string).collect::<Vec<String>>();
+            moves.sort();
+            moves
+        };
+        for fen in [
+            // Rook check that can be blocked or taken, with castling ruled out
+            "4k3/8/8/8/4r3/8/3N1B2/R3K3 w Q - 0 1",
+            // Knight check, only taking it or moving the king helps
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2Nn1Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            // The checking pawn can be taken en passant
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+            // A pinned piece can't block
+            "4k3/8/8/b7/8/8/3R4/r3K3 w - - 0 1",
+        ] {
+            let board = Game::new(fen).unwrap().board;
+            assert!(board.in_check(), "{fen}");
+            assert_eq!(
+                sorted(board.gen_check_evasions()),
+                sorted(board.gen_legal_moves().unwrap()),
+                "{fen}"
+            );
+        }
+
+        // Double check by the rook and the bishop, only the king moves
+        let board = Game::new("4k3/8/8/8/1b6/8/8/r3K3 w - - 0 1").unwrap().board;
+        let evasions = board.gen_check_evasions();
+        assert!(!evasions.is_empty());
+        assert!(evasions.iter().all(|mov| mov.what.kind == Kind::King));
+        assert_eq!(sorted(evasions), sorted(board.gen_legal_moves().unwrap()));
+    }
+
+    #[test]
+    fn king_danger_squares() {
+        let lans = |fen: &str| {
+            let mut lans = Game::new(fen)
+                .unwrap()
+                .board
+                .generate_king_moves_bulk()
+                .iter()
+                .map(Move::lan_string)
+                .collect::<Vec<String>>();
+            lans.sort();
+            lans
+        };
+        // The rook still covers f1 once the king has left e1
+        assert_eq!(
+            lans("4k3/8/8/8/8/8/8/r3K3 w - - 0 1"),
+            ["e1d2", "e1e2", "e1f2"]
+        );
+        // No stepping next to the other king
+        assert_eq!(lans("8/8/8/8/8/8/4k3/7K b - - 0 1").len(), 8);
+        assert_eq!(lans("8/8/8/8/8/4k3/8/4K3 w - - 0 1"), ["e1d1", "e1f1"]);
+        // Castling through f1 is out, the long side is fine
+        assert!(lans("4kr2/8/8/8/8/8/8/R3K2R w KQ - 0 1").contains(&"e1c1".to_string()));
+        assert!(!lans("4kr2/8/8/8/8/8/8/R3K2R w KQ - 0 1").contains(&"e1g1".to_string()));
+        // And out of check neither
+        let in_check = lans("4r1k1/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        assert!(!in_check.contains(&"e1c1".to_string()));
+        assert!(!in_check.contains(&"e1g1".to_string()));
+    }
+
+    #[test]
+    fn all_captures_match_generated_captures() {
+        // Whole moves are compared, so castling rights and promotions have to match too
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ] {
+            let board = Game::new(fen).unwrap().board;
+            let captures = board.generate_all_captures().unwrap();
+            let expected = board
+                .gen_moves()
+                .unwrap()
+                .into_iter()
+                .filter(Move::is_capture)
+                .collect::<Vec<Move>>();
+            assert_eq!(captures.len(), expected.len(), "{fen}");
+            for mov in &captures {
+                assert!(expected.contains(mov), "{fen} {mov}");
+            }
+        }
+    }
+
+    #[test]
+    fn recaptures() {
+        // After ...Bxc3+, White can take back with the pawn, the knight or the queen
+        let game = Game::new("4k3/8/8/8/8/2b5/1P2N3/2Q1K3 w - - 0 1").unwrap();
+        let c3 = Bitboard::from_algebraic("c3").unwrap();
+        let mut recaptures = game
+            .board
+            .generate_recapture_moves(c3)
+            .iter()
+            .map(Move::lan_string)
+            .collect::<Vec<String>>();
+        recaptures.sort();
+        assert_eq!(recaptures, ["b2c3", "c1c3", "e2c3"]);
+
+        // Nothing to take on an empty square
+        let empty = Bitboard::from_algebraic("c4").unwrap();
+        assert!(game.board.generate_recapture_moves(empty).is_empty());
+    }
+
+    #[test]
+    fn quiet_checks() {
+        // Rh2 checks directly, any knight move uncovers the queen and Rxc8 checks too but takes
+        // the rook
+        let game = Game::new("2r4k/8/8/8/3N4/8/2R5/Q3K3 w - - 0 1").unwrap();
+        let lans = |moves: Vec<Move>| moves.iter().map(Move::lan_string).collect::<Vec<String>>();
+        let quiet_checks = lans(game.board.generate_quiet_checks());
+        for lan in ["c2h2", "d4c6", "d4e6"] {
+            assert!(quiet_checks.contains(&lan.to_string()), "{lan}");
+        }
+        assert!(!quiet_checks.contains(&"c2c3".to_string()));
+        assert!(!quiet_checks.contains(&"c2c8".to_string()));
+        assert!(lans(game.board.generate_checks()).contains(&"c2c8".to_string()));
+    }
+
+    #[test]
+    fn promotions() {
+        let game = Game::new("k7/4P3/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        let promotions = game.board.generate_promotions();
+        assert_eq!(promotions.len(), 1);
+        assert_eq!(promotions[0].lan_string(), "e7e8q");
 
-        Ok(moves.into_iter().filter(|b| !b.to.is_empty()).collect())
+        // Capturing on d8 may under-promote, pushing to e8 only promotes to a queen
+        let game = Game::new("k2r4/4P3/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        let promotions = game.board.generate_promotions();
+        assert_eq!(promotions.len(), 5);
+        assert_eq!(promotions.iter().filter(|mov| mov.is_capture()).count(), 4);
     }
 }
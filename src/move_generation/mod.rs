@@ -1,4 +1,6 @@
 pub mod error;
 mod r#impl;
+mod staged;
 
 pub use r#impl::*;
+pub use staged::StagedMoveGenerator;
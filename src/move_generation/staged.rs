@@ -0,0 +1,198 @@
+use crate::{board::Board, eval::piece_value, piece::Kind, r#move::Move};
+
+use super::Movegen;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Captures,
+    Quiets,
+    Done,
+}
+
+// Legal moves, generated one stage at a time as the caller asks for them. Captures come first,
+// most valuable victim and least valuable attacker first, then the quiet moves. A search that
+// cuts off after the first captures never pays for generating the rest
+pub struct StagedMoveGenerator<'a> {
+    board: &'a Board,
+    stage: Stage,
+    // Pending moves of the current stage, best one last
+    moves: Vec<Move>,
+    captures_only: bool,
+}
+
+impl<'a> StagedMoveGenerator<'a> {
+    pub fn new(board: &'a Board) -> Self {
+        Self {
+            board,
+            stage: Stage::Captures,
+            moves: vec![],
+            captures_only: false,
+        }
+    }
+
+    // Stops after the captures, for quiescence search
+    pub fn captures_only(board: &'a Board) -> Self {
+        Self {
+            captures_only: true,
+            ..Self::new(board)
+        }
+    }
+
+    fn is_legal(&self, mov: Move) -> bool {
+        let mut after = *self.board;
+        after.move_piece(mov);
+        !after.is_check(self.board.turn).unwrap_or(true)
+    }
+
+    fn generate_captures(&mut self) {
+        let mut captures = self.board.generate_all_captures().unwrap_or_default();
+        captures.retain(|&mov| self.is_legal(mov));
+        // MVV-LVA, sorted so the best capture is popped first. Nothing takes back a hanging
+        // piece, so taking one counts its value twice
+        let opponent = !self.board.turn;
+        captures.sort_by_key(|mov| {
+            mov.capture.map_or(0, |capture| {
+                let victim = piece_value(capture.kind) * 10;
+                let hanging = self.board.is_piece_hanging(capture.position, opponent);
+                victim + if hanging { victim } else { 0 }
+            }) - piece_value(mov.what.kind)
+        });
+        self.moves = captures;
+    }
+
+    fn generate_quiets(&mut self) {
+        let mut quiets = self.board.gen_moves().unwrap_or_default();
+        quiets.retain(|&mov| !mov.is_capture() && self.is_legal(mov));
+        // Knight moves that set up a fork go first, the rest keep generation order
+        let forks = self.board.knight_fork_squares(self.board.turn);
+        quiets.reverse();
+        quiets.sort_by_key(|mov| mov.what.kind == Kind::Knight && mov.to.intersects(forks));
+        self.moves = quiets;
+    }
+}
+
+impl Iterator for StagedMoveGenerator<'_> {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        loop {
+            if let Some(mov) = self.moves.pop() {
+                return Some(mov);
+            }
+            match self.stage {
+                Stage::Captures => {
+                    self.generate_captures();
+                    self.stage = if self.captures_only {
+                        Stage::Done
+                    } else {
+                        Stage::Quiets
+                    };
+                }
+                Stage::Quiets => {
+                    self.generate_quiets();
+                    self.stage = Stage::Done;
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+}
+
+impl Board {
+    pub fn generate_moves_staged(&self) -> StagedMoveGenerator<'_> {
+        StagedMoveGenerator::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitboard::{display::BitboardDisplay, Bitboard},
+        Game,
+    };
+
+    #[test]
+    fn staged_matches_legal_moves() {
+        for fen in [
+            Game::STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ] {
+            let board = Game::new(fen).unwrap().board;
+            let sorted = |moves: Vec<Move>| {
+                let mut moves = moves.iter().map(Move::lan_string).collect::<Vec<String>>();
+                moves.sort();
+                moves
+            };
+            let staged = board.generate_moves_staged().collect::<Vec<Move>>();
+            assert_eq!(
+                sorted(staged.clone()),
+                sorted(board.gen_legal_moves().unwrap()),
+                "{fen}"
+            );
+            // Every capture before the first quiet move
+            let captures = staged.iter().filter(|mov| mov.is_capture()).count();
+            assert!(staged[..captures].iter().all(Move::is_capture), "{fen}");
+        }
+    }
+
+    #[test]
+    fn staged_moves_are_generated_moves() {
+        // Every field has to match, the castling rights lost by a corner rook's capture included
+        for fen in [
+            "4k3/p7/8/8/8/8/8/R3K3 w Q - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        ] {
+            let board = Game::new(fen).unwrap().board;
+            let moves = board.gen_moves().unwrap();
+            for mov in board.generate_moves_staged() {
+                assert!(moves.contains(&mov), "{fen} {mov}");
+                assert!(board.is_legal_move(mov), "{fen} {mov}");
+            }
+            for mov in board.generate_recapture_moves(Bitboard::from_algebraic("a7").unwrap()) {
+                assert!(moves.contains(&mov), "{fen} {mov}");
+            }
+        }
+    }
+
+    #[test]
+    fn fork_threats_first_among_quiets() {
+        // Nc7 forks the king and the rook, and there's nothing to capture
+        let board = Game::new("r3k3/8/8/1N6/8/8/8/4K3 w - - 0 1").unwrap().board;
+        assert_eq!(
+            board.generate_moves_staged().next().unwrap().lan_string(),
+            "b5c7"
+        );
+    }
+
+    #[test]
+    fn hanging_captures_first() {
+        // The rook on d5 is worth more, but the pawn takes back. Nothing guards the knight on
+        // b4 or the pawn on e6
+        let board = Game::new("6k1/8/4p3/3r4/1n3N2/8/3B4/6K1 w - - 0 1")
+            .unwrap()
+            .board;
+        let captures = StagedMoveGenerator::captures_only(&board)
+            .map(|mov| mov.lan_string())
+            .collect::<Vec<String>>();
+        assert_eq!(captures, ["d2b4", "f4d5", "f4e6"]);
+    }
+
+    #[test]
+    fn captures_ordered_by_mvv_lva() {
+        // The pawn can take the queen or the knight, the rook can take the queen too
+        let board = Game::new("4k3/8/8/2n1q3/3P4/8/8/4RK2 w - - 0 1")
+            .unwrap()
+            .board;
+        let captures = board
+            .generate_moves_staged()
+            .take(3)
+            .map(|mov| mov.lan_string())
+            .collect::<Vec<String>>();
+        assert_eq!(captures, ["d4e5", "e1e5", "d4c5"]);
+        assert_eq!(StagedMoveGenerator::captures_only(&board).count(), 3);
+    }
+}
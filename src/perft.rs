@@ -1,22 +1,62 @@
 use crate::move_generation::Movegen;
+use crate::r#move::Move;
 use crate::Game;
 use rayon::prelude::*;
 
-pub fn perft(game: &mut Game, depth: u8, is_root: bool) -> u64 {
+// Whether the side that just moved left its own king in check
+fn leaves_king_in_check(game: &Game) -> bool {
+    let mover = !game.board.turn;
+    game.board.is_check(mover).unwrap_or(false)
+}
+
+// With `bulk` set, nodes at depth 1 count their legal moves instead of recursing into each one.
+// Per-move counts at the root (divide) are only printed for the moves that get recursed into.
+// Moves that repeat a position for the third time end the game there, so they aren't counted
+fn is_counted_leaf(game: &mut Game, mov: Move) -> bool {
+    game.make_move_unchecked(mov);
+    let legal = !leaves_king_in_check(game);
+    #[cfg(debug_assertions)]
+    if legal {
+        assert_legal_leaf(game);
+    }
+    let counted = legal && !game.is_repetition;
+    game.unmake_move(mov);
+    counted
+}
+
+#[cfg(debug_assertions)]
+fn assert_legal_leaf(game: &Game) {
+    if let Err(violations) = game.board.assert_legal_position() {
+        panic!(
+            "Illegal position reached after {}\n{}\n{}",
+            game.history,
+            violations.join("\n"),
+            game.board
+        );
+    }
+}
+
+pub fn perft(game: &mut Game, depth: u8, is_root: bool, bulk: bool) -> u64 {
     if depth == 0 {
-        return 1;
+        #[cfg(debug_assertions)]
+        assert_legal_leaf(game);
+        // A threefold repetition ends the game, legal moves or not
+        return u64::from(!game.is_repetition);
     }
 
     let moves = game.board.gen_moves().unwrap();
+    if bulk && depth == 1 {
+        return moves.iter().filter(|m| is_counted_leaf(game, **m)).count() as u64;
+    }
     let mut all_nodes = 0;
     for m in &moves {
-        game.make_move(*m);
+        game.make_move_unchecked(*m);
         // println!("{m} {}", game.board);
-        let nodes = if game.is_in_check {
+        let nodes = if leaves_king_in_check(game) {
             // eprintln!("undoing...");
             0
         } else {
-            perft(game, depth - 1, false)
+            perft(game, depth - 1, false, bulk)
         };
         game.unmake_move(*m);
         if is_root && nodes > 0 {
@@ -27,7 +67,61 @@ pub fn perft(game: &mut Game, depth: u8, is_root: bool) -> u64 {
     all_nodes
 }
 
-pub fn perft_parallel(game: &Game, depth: u8, is_root: bool) -> u64 {
+// Perft that checks every make/unmake pair puts the game back exactly as it was, fields that
+// don't change the node count included. Returns the node count
+pub fn perft_consistency_check(game: &mut Game, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let mut all_nodes = 0;
+    for m in game.board.gen_moves().unwrap() {
+        let before = game.clone();
+        game.make_move_unchecked(m);
+        if !leaves_king_in_check(game) {
+            all_nodes += perft_consistency_check(game, depth - 1);
+        }
+        game.unmake_move(m);
+        assert_eq!(
+            *game, before,
+            "Unmaking {m} didn't restore the game after {}",
+            game.history
+        );
+    }
+    all_nodes
+}
+
+// Perft that recomputes the Zobrist hash from scratch at every node and after every unmake, and
+// panics with the moves that led there when the incremental one went wrong. Returns the node
+// count. Debug builds only, it's far too slow for anything else
+#[cfg(debug_assertions)]
+pub fn perft_hash_check(game: &mut Game, depth: u8) -> u64 {
+    assert_eq!(
+        game.board.hash(),
+        game.board.zobrist_hash_from_scratch(),
+        "Incremental hash diverged after {}",
+        game.history
+    );
+    if depth == 0 {
+        return 1;
+    }
+    let mut all_nodes = 0;
+    for m in game.board.gen_moves().unwrap() {
+        game.make_move_unchecked(m);
+        if !leaves_king_in_check(game) {
+            all_nodes += perft_hash_check(game, depth - 1);
+        }
+        game.unmake_move(m);
+        assert_eq!(
+            game.board.hash(),
+            game.board.zobrist_hash_from_scratch(),
+            "Unmaking {m} didn't restore the hash after {}",
+            game.history
+        );
+    }
+    all_nodes
+}
+
+pub fn perft_parallel(game: &Game, depth: u8, is_root: bool, bulk: bool) -> u64 {
     if depth == 0 {
         return 1;
     }
@@ -37,11 +131,11 @@ pub fn perft_parallel(game: &Game, depth: u8, is_root: bool) -> u64 {
         .map_init(
             || game.clone(), // Initialize a clone of the game for each thread
             |game_clone, m| {
-                game_clone.make_move(*m);
-                let nodes = if game_clone.is_in_check {
+                game_clone.make_move_unchecked(*m);
+                let nodes = if leaves_king_in_check(game_clone) {
                     0
                 } else {
-                    perft(game_clone, depth - 1, false)
+                    perft(game_clone, depth - 1, false, bulk)
                 };
                 game_clone.unmake_move(*m);
                 if is_root && nodes > 0 {
@@ -66,7 +160,6 @@ mod tests {
     #![allow(dead_code)]
     use super::*;
     use crate::piece::Piece;
-    use crate::r#move::Move;
     use std::mem;
     pub fn sizes() {
         println!("Size of Piece: {}", mem::size_of::<Piece>());
@@ -91,7 +184,7 @@ mod tests {
         let mut game = Game::new(Game::STARTING_FEN).unwrap();
         // TODO: Test all the way down!
         for depth in 1..=4 {
-            let n_moves = perft(&mut game, depth, true);
+            let n_moves = perft(&mut game, depth, true, true);
             assert_eq!(
                 n_moves,
                 PERFT_RESULTS[depth as usize - 1],
@@ -102,4 +195,43 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn repetitions_are_not_counted() {
+        let mut game = Game::default();
+        for lan in ["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1"] {
+            let mov = game.parse_move(lan).unwrap();
+            game.make_move(mov);
+        }
+        // f6g8 brings back the starting position for the third time
+        let legal = game.board.legal_moves_count() as u64;
+        assert_eq!(perft(&mut game, 1, false, true), legal - 1);
+        assert_eq!(perft(&mut game, 1, false, false), legal - 1);
+    }
+
+    #[test]
+    fn make_unmake_consistency() {
+        // https://www.chessprogramming.org/Perft_Results
+        for (fen, nodes) in [
+            (Game::STARTING_FEN, 8902),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                97862,
+            ),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 2812),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                9467,
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                62379,
+            ),
+        ] {
+            let mut game = Game::new(fen).unwrap();
+            assert_eq!(perft_consistency_check(&mut game, 3), nodes, "{fen}");
+            #[cfg(debug_assertions)]
+            assert_eq!(perft_hash_check(&mut game, 3), nodes, "{fen}");
+        }
+    }
 }
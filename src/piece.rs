@@ -1,8 +1,11 @@
 use std::ops::Not;
+use std::str::FromStr;
 
 use crate::bitboard::Bitboard;
+use crate::game::FenError;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Kind {
     Pawn,
     Knight,
@@ -12,7 +15,8 @@ pub enum Kind {
     King,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Color {
     White,
     Black,
@@ -29,6 +33,7 @@ impl Not for Color {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Piece {
     pub color: Color,
     pub kind: Kind,
@@ -41,9 +46,40 @@ impl std::fmt::Display for Piece {
     }
 }
 
+// Human readable, use `to_fen_char` for FEN
 impl std::fmt::Display for Color {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{self:?}")
+        match self {
+            Self::White => write!(f, "White"),
+            Self::Black => write!(f, "Black"),
+        }
+    }
+}
+
+impl Color {
+    // Active color field of a FEN string
+    #[must_use]
+    pub const fn to_fen_char(self) -> char {
+        match self {
+            Self::White => 'w',
+            Self::Black => 'b',
+        }
+    }
+
+    pub fn from_fen_str(s: &str) -> Result<Self, FenError> {
+        match s {
+            "w" => Ok(Self::White),
+            "b" => Ok(Self::Black),
+            _ => Err(FenError::InvalidColor(s.to_string())),
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = FenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_fen_str(s)
     }
 }
 
@@ -53,6 +89,27 @@ impl std::fmt::Display for Kind {
     }
 }
 
+impl Kind {
+    // Kinds a pawn can turn into when it reaches the last rank
+    #[must_use]
+    pub const fn is_promotable(self) -> bool {
+        matches!(self, Self::Knight | Self::Bishop | Self::Rook | Self::Queen)
+    }
+
+    // Uppercase letter used by FEN and SAN
+    #[must_use]
+    pub const fn to_letter(self) -> char {
+        match self {
+            Self::Pawn => 'P',
+            Self::Knight => 'N',
+            Self::Bishop => 'B',
+            Self::Rook => 'R',
+            Self::Queen => 'Q',
+            Self::King => 'K',
+        }
+    }
+}
+
 impl Piece {
     #[must_use]
     pub const fn new(color: Color, kind: Kind, position: Bitboard) -> Self {
@@ -62,18 +119,23 @@ impl Piece {
             position,
         }
     }
+
+    // Same piece on another square
+    #[must_use]
+    pub const fn on_square(&self, square: Bitboard) -> Self {
+        Self::new(self.color, self.kind, square)
+    }
+
+    // Same color and square, different kind. Mostly for promotions
+    #[must_use]
+    pub const fn with_kind(&self, kind: Kind) -> Self {
+        Self::new(self.color, kind, self.position)
+    }
 }
 
 #[must_use]
 pub fn to_letter(piece: Option<Piece>) -> char {
-    let mut c: char = piece.map_or('.', |piece| match piece.kind {
-        Kind::Pawn => 'P',
-        Kind::Knight => 'N',
-        Kind::Bishop => 'B',
-        Kind::Rook => 'R',
-        Kind::Queen => 'Q',
-        Kind::King => 'K',
-    });
+    let mut c: char = piece.map_or('.', |piece| piece.kind.to_letter());
     if let Some(piece) = piece {
         c = match piece.color {
             Color::White => c,
@@ -0,0 +1,458 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    eval::{evaluate, MATE_SCORE},
+    move_generation::StagedMoveGenerator,
+    r#move::Move,
+    tt::{NodeType, TTEntry, TranspositionTable},
+    Game,
+};
+
+// Time a search gets when the caller doesn't say
+pub const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(5);
+// Entries, a few tens of megabytes
+pub const DEFAULT_TT_SIZE: usize = 1 << 18;
+// Scores this close to `MATE_SCORE` are mates, with the distance in plies taken off
+const MATE_THRESHOLD: i32 = MATE_SCORE - 1000;
+const INFINITY: i32 = MATE_SCORE + 1;
+// Half width of the first window around the last iteration's score, in centipawns. Each fail
+// widens it by `ASPIRATION_GROWTH`, until it's past `MAX_ASPIRATION_WINDOW` and the full window is
+// used instead
+const ASPIRATION_WINDOW: i32 = 50;
+const ASPIRATION_GROWTH: i32 = 4;
+const MAX_ASPIRATION_WINDOW: i32 = 200;
+// Looking at the clock on every node would cost more than the nodes themselves
+const NODES_BETWEEN_TIME_CHECKS: u64 = 2048;
+
+// Best move and its score for the side to move, searching up to `max_depth` plies or for
+// `DEFAULT_MOVE_TIME`, whichever comes first. Panics if there are no legal moves
+pub fn search(game: &mut Game, max_depth: u8) -> (Move, SearchScore) {
+    Searcher::new(DEFAULT_TT_SIZE).search(game, max_depth, DEFAULT_MOVE_TIME)
+}
+
+// Iterative deepening over a fail-soft negamax alpha-beta. The transposition table is kept
+// between searches
+pub struct Searcher {
+    tt: TranspositionTable,
+    nodes: u64,
+    // None when there's no time limit
+    deadline: Option<Instant>,
+    // Set from another thread to end the search early
+    stop_signal: Option<Arc<AtomicBool>>,
+    // Only once the first depth is done, so there's always a move to return
+    can_stop: bool,
+    stopped: bool,
+}
+
+impl Searcher {
+    pub fn new(tt_size: usize) -> Self {
+        Self {
+            tt: TranspositionTable::new(tt_size),
+            nodes: 0,
+            deadline: None,
+            stop_signal: None,
+            can_stop: false,
+            stopped: false,
+        }
+    }
+
+    pub fn set_stop_signal(&mut self, stop_signal: Arc<AtomicBool>) {
+        self.stop_signal = Some(stop_signal);
+    }
+
+    // Replaces the transposition table with an empty one of about `mb` megabytes
+    pub fn set_hash_size(&mut self, mb: usize) {
+        self.tt = TranspositionTable::with_size_mb(mb);
+    }
+
+    pub fn hash_entries(&self) -> usize {
+        self.tt.len()
+    }
+
+    // Forgets everything learned in earlier searches
+    pub fn clear(&mut self) {
+        self.tt.clear();
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    // Searches one more ply per iteration, printing a UCI info line after each one. When time
+    // runs out mid-iteration, a root move that already beat the last iteration's best is kept
+    pub fn search(
+        &mut self,
+        game: &mut Game,
+        max_depth: u8,
+        time_limit: Duration,
+    ) -> (Move, SearchScore) {
+        let start = Instant::now();
+        self.tt.new_search();
+        self.nodes = 0;
+        // Too far in the future to fit in an `Instant` means no limit
+        self.deadline = start.checked_add(time_limit);
+        self.can_stop = false;
+        self.stopped = false;
+
+        let mut best = None;
+        let mut pv = vec![];
+        for depth in 1..=max_depth.max(1) {
+            let score = match best {
+                Some((_, prev_score)) => {
+                    self.search_aspiration(game, depth, prev_score, ASPIRATION_WINDOW, &mut pv)
+                }
+                None => self.negamax(game, depth, 0, -INFINITY, INFINITY, &mut pv),
+            };
+            if let Some(&mov) = pv.first() {
+                best = Some((mov, score));
+            }
+            if self.stopped {
+                break;
+            }
+            let elapsed = start.elapsed();
+            println!(
+                "info depth {depth} score {} nodes {} time {} pv {}",
+                SearchScore::from(score),
+                self.nodes,
+                elapsed.as_millis(),
+                pv.iter()
+                    .map(Move::lan_string)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+            if elapsed >= time_limit {
+                break;
+            }
+            self.can_stop = true;
+        }
+        let (mov, score) = best.expect("No legal moves to search");
+        (mov, SearchScore::from(score))
+    }
+
+    // Searches `depth` with a window of `window` either side of `prev_score`, on the bet that the
+    // score won't move much between iterations. A score outside the window only says which side
+    // it's on, so that side gets widened and searched again
+    fn search_aspiration(
+        &mut self,
+        game: &mut Game,
+        depth: u8,
+        prev_score: i32,
+        mut window: i32,
+        pv: &mut Vec<Move>,
+    ) -> i32 {
+        let mut alpha = prev_score.saturating_sub(window).max(-INFINITY);
+        let mut beta = prev_score.saturating_add(window).min(INFINITY);
+        loop {
+            let score = self.negamax(game, depth, 0, alpha, beta, pv);
+            if self.stopped || (score > alpha && score < beta) {
+                return score;
+            }
+            window *= ASPIRATION_GROWTH;
+            let bound = |score: i32, side: i32| {
+                if window > MAX_ASPIRATION_WINDOW {
+                    side * INFINITY
+                } else {
+                    score + side * window
+                }
+            };
+            if score <= alpha {
+                alpha = bound(score, -1).max(-INFINITY);
+            } else {
+                beta = bound(score, 1).min(INFINITY);
+            }
+        }
+    }
+
+    fn out_of_time(&mut self) -> bool {
+        if self.can_stop && self.nodes.is_multiple_of(NODES_BETWEEN_TIME_CHECKS) {
+            let timed_out = self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline);
+            let told_to_stop = self
+                .stop_signal
+                .as_ref()
+                .is_some_and(|stop_signal| stop_signal.load(Ordering::Relaxed));
+            self.stopped = timed_out || told_to_stop;
+        }
+        self.stopped
+    }
+
+    // `pv` gets the best line from here. Once the search is stopped the score is meaningless,
+    // except at the root, where it's the best of the moves that were fully searched
+    fn negamax(
+        &mut self,
+        game: &mut Game,
+        depth: u8,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+        pv: &mut Vec<Move>,
+    ) -> i32 {
+        pv.clear();
+        if self.out_of_time() {
+            return 0;
+        }
+        self.nodes += 1;
+        if ply > 0 && (game.is_repetition || game.board.is_draw_by_insufficient_material()) {
+            return 0;
+        }
+        // Mate on the hundredth halfmove still counts as mate
+        if ply > 0 && game.is_fifty_move_draw() {
+            return if game.is_checkmate() {
+                -MATE_SCORE + ply as i32
+            } else {
+                0
+            };
+        }
+        if depth == 0 {
+            return self.quiescence(game, alpha, beta);
+        }
+
+        let hash = game.board.hash();
+        let tt_entry = self.tt.probe(hash);
+        if let Some(entry) = tt_entry.filter(|entry| ply > 0 && entry.depth >= depth) {
+            let score = score_from_tt(entry.score, ply);
+            match entry.node_type {
+                NodeType::Exact => return score,
+                NodeType::LowerBound if score >= beta => return score,
+                NodeType::UpperBound if score <= alpha => return score,
+                _ => {}
+            }
+        }
+
+        // The best move from an earlier search goes first, before generating anything. The rest
+        // come from the staged generator, only as far as the search gets before a cutoff
+        let tt_move = tt_entry
+            .and_then(|entry| entry.best_move)
+            .filter(|&mov| game.board.is_legal_move(mov));
+        let board = game.board;
+        let moves = tt_move.into_iter().chain(
+            board
+                .generate_moves_staged()
+                .filter(|&mov| Some(mov) != tt_move),
+        );
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut searched_any = false;
+        let mut child_pv = vec![];
+        for mov in moves {
+            searched_any = true;
+            game.make_move(mov);
+            let score = -self.negamax(game, depth - 1, ply + 1, -beta, -alpha, &mut child_pv);
+            game.unmake_move(mov);
+            if self.stopped {
+                break;
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = Some(mov);
+                if score > alpha {
+                    alpha = score;
+                    pv.clear();
+                    pv.push(mov);
+                    pv.extend_from_slice(&child_pv);
+                }
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        if self.stopped {
+            return best_score;
+        }
+        if !searched_any {
+            return if game.board.in_check() {
+                -MATE_SCORE + ply as i32
+            } else {
+                0
+            };
+        }
+
+        let node_type = if best_score >= beta {
+            NodeType::LowerBound
+        } else if best_score > original_alpha {
+            NodeType::Exact
+        } else {
+            NodeType::UpperBound
+        };
+        self.tt.store(
+            hash,
+            TTEntry::new(depth, score_to_tt(best_score, ply), node_type, best_move),
+        );
+        best_score
+    }
+
+    // Captures only, until the position is quiet enough to trust the evaluation
+    fn quiescence(&mut self, game: &mut Game, mut alpha: i32, beta: i32) -> i32 {
+        if self.out_of_time() {
+            return 0;
+        }
+        self.nodes += 1;
+        let stand_pat = evaluate(&game.board);
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut best_score = stand_pat;
+        let board = game.board;
+        for mov in StagedMoveGenerator::captures_only(&board) {
+            game.make_move(mov);
+            let score = -self.quiescence(game, -beta, -alpha);
+            game.unmake_move(mov);
+            if self.stopped {
+                return best_score;
+            }
+            if score > best_score {
+                best_score = score;
+                alpha = alpha.max(score);
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        best_score
+    }
+}
+
+// Mate scores count plies from the root, the table stores them counting from the node
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    match score {
+        _ if score >= MATE_THRESHOLD => score + ply as i32,
+        _ if score <= -MATE_THRESHOLD => score - ply as i32,
+        _ => score,
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    match score {
+        _ if score >= MATE_THRESHOLD => score - ply as i32,
+        _ if score <= -MATE_THRESHOLD => score + ply as i32,
+        _ => score,
+    }
+}
+
+// A search result the way UCI reports it: centipawns for the side to move, or the moves until
+// mate, negative when the side to move gets mated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchScore {
+    Centipawns(i32),
+    Mate(i8),
+}
+
+impl From<i32> for SearchScore {
+    fn from(score: i32) -> Self {
+        if score.abs() >= MATE_THRESHOLD {
+            let moves = (MATE_SCORE - score.abs() + 1) / 2;
+            Self::Mate((moves * score.signum()) as i8)
+        } else {
+            Self::Centipawns(score)
+        }
+    }
+}
+
+impl std::fmt::Display for SearchScore {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Centipawns(score) => write!(f, "cp {score}"),
+            Self::Mate(moves) => write!(f, "mate {moves}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::move_generation::Movegen;
+
+    #[test]
+    fn mate_in_one() {
+        // Back rank mate with the rook
+        let mut game = Game::new("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let (mov, score) = search(&mut game, 3);
+        assert_eq!(mov.lan_string(), "a1a8");
+        assert_eq!(score, SearchScore::Mate(1));
+    }
+
+    #[test]
+    fn takes_hanging_queen() {
+        let mut game = Game::new("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+        let before = game.clone();
+        let (mov, score) = search(&mut game, 3);
+        assert_eq!(mov.lan_string(), "d2d5");
+        assert!(matches!(score, SearchScore::Centipawns(score) if score > 0));
+        // The search walks the tree with make and unmake, and leaves the game as it found it
+        assert_eq!(game, before);
+    }
+
+    #[test]
+    fn fifty_move_draw() {
+        // A queen up, but every move reaches the hundredth halfmove without resetting the clock
+        let mut game = Game::new("4k3/8/8/8/8/8/8/3QK3 w - - 99 80").unwrap();
+        let (_, score) = search(&mut game, 3);
+        assert_eq!(score, SearchScore::Centipawns(0));
+    }
+
+    #[test]
+    fn mate_on_the_hundredth_halfmove() {
+        // The mating move is also the one that reaches the fifty-move limit, mate comes first
+        let mut game = Game::new("6k1/5ppp/8/8/8/8/8/R5K1 w - - 99 80").unwrap();
+        let (mov, score) = search(&mut game, 3);
+        assert_eq!(mov.lan_string(), "a1a8");
+        assert_eq!(score, SearchScore::Mate(1));
+    }
+
+    #[test]
+    fn out_of_time() {
+        // Even without any time there's a move from the first iteration
+        let mut game = Game::default();
+        let (mov, _) = Searcher::new(1 << 10).search(&mut game, 20, Duration::ZERO);
+        assert!(game.board.gen_legal_moves().unwrap().contains(&mov));
+    }
+
+    #[test]
+    fn aspiration_matches_full_window() {
+        for fen in [
+            Game::STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1",
+            "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+        ] {
+            let mut game = Game::new(fen).unwrap();
+            let mut full = Searcher::new(1 << 12);
+            let expected = full.negamax(&mut game, 3, 0, -INFINITY, INFINITY, &mut vec![]);
+            // Windows too narrow on either side, and one the score falls in
+            for prev_score in [expected - 500, expected + 500, expected] {
+                let mut aspiration = Searcher::new(1 << 12);
+                let mut pv = vec![];
+                let score = aspiration.search_aspiration(
+                    &mut game,
+                    3,
+                    prev_score,
+                    ASPIRATION_WINDOW,
+                    &mut pv,
+                );
+                assert_eq!(score, expected, "{fen} from {prev_score}");
+                assert!(!pv.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn uci_scores() {
+        let uci_score = |score: i32| SearchScore::from(score).to_string();
+        assert_eq!(uci_score(35), "cp 35");
+        assert_eq!(uci_score(-MATE_THRESHOLD + 1), "cp -98999");
+        assert_eq!(SearchScore::from(MATE_SCORE - 1), SearchScore::Mate(1));
+        assert_eq!(uci_score(MATE_SCORE - 3), "mate 2");
+        assert_eq!(uci_score(-MATE_SCORE + 2), "mate -1");
+    }
+}
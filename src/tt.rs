@@ -0,0 +1,163 @@
+use crate::r#move::Move;
+
+// How a stored score relates to the real one, depending on where the window cut the search
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeType {
+    // Searched inside the window, the score is exact
+    Exact,
+    // Failed high, the real score is at least this
+    LowerBound,
+    // Failed low, the real score is at most this
+    UpperBound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TTEntry {
+    // Full hash, to tell apart the positions that share a slot
+    pub key: u64,
+    pub depth: u8,
+    pub score: i32,
+    pub node_type: NodeType,
+    pub best_move: Option<Move>,
+    // Search the entry was stored in
+    age: u8,
+}
+
+impl TTEntry {
+    // `key` and `age` are filled in by `TranspositionTable::store`
+    pub fn new(depth: u8, score: i32, node_type: NodeType, best_move: Option<Move>) -> Self {
+        Self {
+            key: 0,
+            depth,
+            score,
+            node_type,
+            best_move,
+            age: 0,
+        }
+    }
+}
+
+// Results of earlier searches, indexed by Zobrist hash. The size is a power of two so the slot
+// is just the low bits of the hash
+pub struct TranspositionTable {
+    entries: Vec<Option<TTEntry>>,
+    age: u8,
+}
+
+impl TranspositionTable {
+    // Rounds `size` up to the next power of two
+    pub fn new(size: usize) -> Self {
+        Self {
+            entries: vec![None; size.max(1).next_power_of_two()],
+            age: 0,
+        }
+    }
+
+    // The most entries that fit in `mb` megabytes, rounded down to a power of two
+    pub fn with_size_mb(mb: usize) -> Self {
+        let entries = (mb.max(1) << 20) / std::mem::size_of::<Option<TTEntry>>();
+        Self::new((entries / 2 + 1).next_power_of_two())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    #[inline(always)]
+    fn index(&self, hash: u64) -> usize {
+        hash as usize & (self.entries.len() - 1)
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<TTEntry> {
+        self.entries[self.index(hash)].filter(|entry| entry.key == hash)
+    }
+
+    // Entries from earlier searches always make room. Within the same search, a shallower
+    // result doesn't replace a deeper one of another position
+    pub fn store(&mut self, hash: u64, entry: TTEntry) {
+        let age = self.age;
+        let index = self.index(hash);
+        let slot = &mut self.entries[index];
+        let replace = match slot {
+            None => true,
+            Some(old) => old.key == hash || old.age != age || entry.depth >= old.depth,
+        };
+        if replace {
+            *slot = Some(TTEntry {
+                key: hash,
+                age,
+                ..entry
+            });
+        }
+    }
+
+    // Call before each new search, so the entries left over become the first to go
+    pub fn new_search(&mut self) {
+        self.age = self.age.wrapping_add(1);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+        self.age = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn power_of_two_size() {
+        assert_eq!(TranspositionTable::new(1000).len(), 1024);
+        assert_eq!(TranspositionTable::new(1024).len(), 1024);
+        assert_eq!(TranspositionTable::new(0).len(), 1);
+    }
+
+    #[test]
+    fn size_in_megabytes() {
+        let entry_size = std::mem::size_of::<Option<TTEntry>>();
+        for mb in [1, 16, 100] {
+            let tt = TranspositionTable::with_size_mb(mb);
+            assert!(tt.len() * entry_size <= mb << 20, "{mb}MB");
+            assert!(tt.len() * 2 * entry_size > mb << 20, "{mb}MB");
+        }
+    }
+
+    #[test]
+    fn probe_and_store() {
+        let mut tt = TranspositionTable::new(16);
+        assert_eq!(tt.probe(3), None);
+        tt.store(3, TTEntry::new(4, 25, NodeType::Exact, None));
+        let entry = tt.probe(3).unwrap();
+        assert_eq!((entry.key, entry.depth, entry.score), (3, 4, 25));
+        // Same slot, different position
+        assert_eq!(tt.probe(3 + 16), None);
+
+        tt.clear();
+        assert_eq!(tt.probe(3), None);
+    }
+
+    #[test]
+    fn replacement() {
+        let mut tt = TranspositionTable::new(16);
+        tt.store(5, TTEntry::new(6, 0, NodeType::Exact, None));
+        // A shallower entry for another position doesn't replace a deeper one
+        tt.store(5 + 16, TTEntry::new(2, 0, NodeType::LowerBound, None));
+        assert_eq!(tt.probe(5).unwrap().depth, 6);
+        assert_eq!(tt.probe(5 + 16), None);
+        // The same position always gets updated
+        tt.store(5, TTEntry::new(1, 10, NodeType::UpperBound, None));
+        assert_eq!(tt.probe(5).unwrap().score, 10);
+
+        // Once a new search starts, anything from the old one can go
+        tt.store(5, TTEntry::new(6, 0, NodeType::Exact, None));
+        tt.new_search();
+        tt.store(5 + 16, TTEntry::new(2, 0, NodeType::LowerBound, None));
+        assert_eq!(tt.probe(5), None);
+        assert_eq!(tt.probe(5 + 16).unwrap().depth, 2);
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    io::BufRead,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::{
+    piece::Color,
+    search::{Searcher, DEFAULT_MOVE_TIME},
+    Game,
+};
+
+const ENGINE_NAME: &str = "rust-chess";
+const ENGINE_AUTHOR: &str = "singiamtel";
+// Deepest a search goes when only time limits it
+const MAX_DEPTH: u8 = 64;
+// Transposition table size in megabytes, and the range the `Hash` option allows
+const DEFAULT_HASH_MB: usize = 16;
+const MAX_HASH_MB: usize = 1024;
+// Share of the remaining clock spent on one move, when the GUI sends `wtime` and `btime`
+const MOVES_TO_GO: u64 = 30;
+
+// Reads UCI commands from stdin until `quit` or the end of input. A search still running at the
+// end of input gets to finish
+pub fn uci_loop() {
+    let mut uci = Uci::new();
+    for line in std::io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if !uci.handle(&line) {
+            uci.stop();
+            return;
+        }
+    }
+    uci.wait();
+}
+
+// Limits from a `go` command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GoLimits {
+    pub depth: u8,
+    pub move_time: Duration,
+}
+
+impl GoLimits {
+    // `go depth N`, `go movetime N`, `go infinite` and the clock fields. Plain `go` gets
+    // `DEFAULT_MOVE_TIME`
+    pub fn parse(args: &str, game: &Game) -> Self {
+        let mut limits = Self {
+            depth: MAX_DEPTH,
+            move_time: DEFAULT_MOVE_TIME,
+        };
+        let mut clock = None;
+        let mut increment = 0;
+        let mut tokens = args.split_whitespace();
+        while let Some(token) = tokens.next() {
+            let mut value = || tokens.next().and_then(|value| value.parse::<u64>().ok());
+            match (token, game.turn()) {
+                ("depth", _) => {
+                    limits.depth =
+                        value().map_or(MAX_DEPTH, |depth| depth.min(MAX_DEPTH as u64) as u8);
+                    limits.move_time = Duration::MAX;
+                }
+                ("movetime", _) => {
+                    limits.move_time = Duration::from_millis(value().unwrap_or(0));
+                }
+                ("infinite", _) => limits.move_time = Duration::MAX,
+                ("wtime", Color::White) | ("btime", Color::Black) => {
+                    clock = value();
+                }
+                ("winc", Color::White) | ("binc", Color::Black) => {
+                    increment = value().unwrap_or(0);
+                }
+                _ => {}
+            }
+        }
+        if let Some(clock) = clock {
+            limits.move_time = Duration::from_millis(clock / MOVES_TO_GO + increment / 2);
+        }
+        limits
+    }
+}
+
+// State kept between commands. Searches run on their own thread so `stop` can reach them
+pub struct Uci {
+    game: Game,
+    // Taken by the search thread and handed back when it's done
+    searcher: Option<Searcher>,
+    search_thread: Option<JoinHandle<Searcher>>,
+    stop_signal: Arc<AtomicBool>,
+    // Megabytes for the transposition table, from the `Hash` option
+    hash_mb: usize,
+}
+
+fn new_searcher(hash_mb: usize) -> Searcher {
+    let mut searcher = Searcher::new(1);
+    searcher.set_hash_size(hash_mb);
+    searcher
+}
+
+impl Default for Uci {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Uci {
+    pub fn new() -> Self {
+        Self {
+            game: Game::default(),
+            searcher: Some(new_searcher(DEFAULT_HASH_MB)),
+            search_thread: None,
+            stop_signal: Arc::new(AtomicBool::new(false)),
+            hash_mb: DEFAULT_HASH_MB,
+        }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    // Returns false once the engine should exit
+    pub fn handle(&mut self, line: &str) -> bool {
+        let line = line.trim();
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        match command {
+            "uci" => {
+                println!("id name {ENGINE_NAME}");
+                println!("id author {ENGINE_AUTHOR}");
+                println!(
+                    "option name Hash type spin default {DEFAULT_HASH_MB} min 1 max {MAX_HASH_MB}"
+                );
+                println!("option name Clear Hash type button");
+                println!("uciok");
+            }
+            "isready" => println!("readyok"),
+            "ucinewgame" => {
+                self.stop();
+                self.game = Game::default();
+                if let Some(searcher) = &mut self.searcher {
+                    searcher.clear();
+                }
+            }
+            "position" => {
+                self.stop();
+                if let Err(err) = self.set_position(args) {
+                    println!("info string {err}");
+                }
+            }
+            "go" => {
+                self.stop();
+                self.go(GoLimits::parse(args, &self.game));
+            }
+            "setoption" => {
+                self.stop();
+                if let Err(err) = self.set_option(args) {
+                    println!("info string {err}");
+                }
+            }
+            "stop" => self.stop(),
+            "quit" => return false,
+            _ => {}
+        }
+        true
+    }
+
+    // `startpos` or `fen <fen>`, optionally followed by `moves` and the moves in LAN. The
+    // position is left alone if anything fails to parse
+    pub fn set_position(&mut self, args: &str) -> Result<(), String> {
+        let (position, moves) = match args.split_once("moves") {
+            Some((position, moves)) => (position.trim(), moves),
+            None => (args.trim(), ""),
+        };
+        let fen = match position.split_once(' ') {
+            _ if position == "startpos" => Game::STARTING_FEN,
+            Some(("fen", fen)) if fen.split_whitespace().count() >= 4 => fen.trim(),
+            _ => return Err(format!("Invalid position: {args}")),
+        };
+        let mut game = Game::new(fen).map_err(|err| err.to_string())?;
+        for lan in moves.split_whitespace() {
+            let mov = game
+                .parse_move(lan)
+                .map_err(|err| format!("Invalid move {lan}: {err}"))?;
+            game.make_move(mov);
+        }
+        self.game = game;
+        Ok(())
+    }
+
+    // `name <name> [value <value>]`, for the options listed in the `uci` reply
+    pub fn set_option(&mut self, args: &str) -> Result<(), String> {
+        let args = args.trim().strip_prefix("name ").unwrap_or(args);
+        let (name, value) = match args.split_once(" value ") {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (args.trim(), None),
+        };
+        let searcher = self
+            .searcher
+            .get_or_insert_with(|| new_searcher(self.hash_mb));
+        match name.to_lowercase().as_str() {
+            "hash" => {
+                let mb = value
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .ok_or_else(|| format!("Invalid Hash value: {args}"))?;
+                self.hash_mb = mb.clamp(1, MAX_HASH_MB);
+                searcher.set_hash_size(self.hash_mb);
+            }
+            "clear hash" => searcher.clear(),
+            _ => return Err(format!("Unknown option: {name}")),
+        }
+        Ok(())
+    }
+
+    fn go(&mut self, limits: GoLimits) {
+        let Some(mut searcher) = self.searcher.take() else {
+            return;
+        };
+        self.stop_signal.store(false, Ordering::Relaxed);
+        searcher.set_stop_signal(Arc::clone(&self.stop_signal));
+        let mut game = self.game.clone();
+        self.search_thread = Some(std::thread::spawn(move || {
+            if game.board.legal_moves_count() == 0 {
+                println!("bestmove 0000");
+            } else {
+                let (mov, _) = searcher.search(&mut game, limits.depth, limits.move_time);
+                println!("bestmove {}", mov.lan_string());
+            }
+            searcher
+        }));
+    }
+
+    // Ends the running search, if any, and waits for its `bestmove`
+    pub fn stop(&mut self) {
+        self.stop_signal.store(true, Ordering::Relaxed);
+        self.wait();
+    }
+
+    // Waits for the running search, if any, to finish on its own
+    pub fn wait(&mut self) {
+        if let Some(search_thread) = self.search_thread.take() {
+            self.searcher = search_thread.join().ok();
+        }
+        // A search that panicked takes its searcher along
+        self.searcher
+            .get_or_insert_with(|| new_searcher(self.hash_mb));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position() {
+        let mut uci = Uci::new();
+        assert!(uci.handle("position startpos moves e2e4 e7e5 g1f3"));
+        assert_eq!(
+            uci.game().to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        uci.handle(&format!("position fen {fen}"));
+        assert_eq!(uci.game().to_fen(), fen);
+        uci.handle(&format!("position fen {fen} moves e1g1"));
+        assert_eq!(
+            uci.game().to_fen(),
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R4RK1 b kq - 1 1"
+        );
+
+        // Bad input leaves the position as it was
+        assert!(uci.set_position("startpos moves e2e5").is_err());
+        assert!(uci.set_position("fen 8/8").is_err());
+        assert!(uci.set_position("somewhere").is_err());
+        assert!(uci.set_position("fen 8/8/8/8/8/8/8/8 w - - x y").is_err());
+        assert!(uci.handle("position fen k7/8/8/8/8/8/8/K7 w X - 0 1"));
+        assert!(uci
+            .set_position("fen KK6/8/8/8/8/8/8/k7 w - - 0 1")
+            .is_err());
+        assert!(uci
+            .set_position("fen 4k3/8/8/8/8/8/8/4RK2 w - - 0 1")
+            .is_err());
+        assert_eq!(
+            uci.game().to_fen(),
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R4RK1 b kq - 1 1"
+        );
+    }
+
+    #[test]
+    fn go_limits() {
+        let game = Game::default();
+        let parse = |args: &str| GoLimits::parse(args, &game);
+        assert_eq!(parse("depth 5").depth, 5);
+        assert_eq!(parse("depth 5").move_time, Duration::MAX);
+        assert_eq!(parse("movetime 250").move_time, Duration::from_millis(250));
+        assert_eq!(parse("movetime 250").depth, MAX_DEPTH);
+        assert_eq!(parse("infinite").move_time, Duration::MAX);
+        assert_eq!(parse("").move_time, DEFAULT_MOVE_TIME);
+        // White to move, so only White's clock counts
+        assert_eq!(
+            parse("wtime 30000 btime 1000 winc 1000 binc 0").move_time,
+            Duration::from_millis(1500)
+        );
+    }
+
+    #[test]
+    fn options() {
+        let mut uci = Uci::new();
+        let entries = |uci: &Uci| uci.searcher.as_ref().unwrap().hash_entries();
+        let default_entries = entries(&uci);
+        assert!(uci.handle("setoption name Hash value 64"));
+        assert_eq!(uci.hash_mb, 64);
+        assert_eq!(entries(&uci), default_entries * 4);
+        // Sizes out of range are clamped, and bad values leave the table alone
+        uci.handle("setoption name Hash value 100000");
+        assert_eq!(uci.hash_mb, MAX_HASH_MB);
+        assert!(uci.set_option("name Hash value lots").is_err());
+        assert_eq!(uci.hash_mb, MAX_HASH_MB);
+
+        uci.handle("setoption name Hash value 1");
+        uci.handle("position startpos");
+        uci.handle("go depth 2");
+        uci.wait();
+        assert!(uci.set_option("name Clear Hash").is_ok());
+        assert!(uci.set_option("name Ponder value true").is_err());
+    }
+
+    #[test]
+    fn go_and_stop() {
+        let mut uci = Uci::new();
+        uci.handle("position startpos");
+        uci.handle("go infinite");
+        assert!(uci.search_thread.is_some());
+        uci.handle("stop");
+        assert!(uci.search_thread.is_none());
+        assert!(uci.searcher.is_some());
+        assert!(!uci.handle("quit"));
+    }
+}
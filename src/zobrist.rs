@@ -0,0 +1,136 @@
+use std::{
+    collections::HashMap,
+    hash::{BuildHasherDefault, Hasher},
+};
+
+use crate::{
+    bitboard::Bitboard,
+    board::CastlingRights,
+    piece::{Color, Piece},
+};
+
+// Random keys for Zobrist hashing. A position's hash is the XOR of the keys for everything in
+// it, so making a move only has to XOR out what changed and XOR in the new state
+pub struct ZobristKeys {
+    // Indexed by color, kind and square
+    pieces: [[[u64; 64]; 6]; 2],
+    // Set when Black is to move
+    side_to_move: u64,
+    // One per combination of rights. No rights is 0, so an empty board hashes to 0
+    castling: [u64; 16],
+    en_passant_file: [u64; 8],
+}
+
+// Generated at compile time from a fixed seed, so hashes stay the same between runs
+pub static ZOBRIST: ZobristKeys = ZobristKeys::generate(0x9E37_79B9_7F4A_7C15);
+
+// xorshift64*, good enough to get well spread keys
+const fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state >> 12;
+    *state ^= *state << 25;
+    *state ^= *state >> 27;
+    state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+}
+
+impl ZobristKeys {
+    const fn generate(seed: u64) -> Self {
+        let mut state = seed;
+        let mut pieces = [[[0; 64]; 6]; 2];
+        let mut color = 0;
+        while color < 2 {
+            let mut kind = 0;
+            while kind < 6 {
+                let mut square = 0;
+                while square < 64 {
+                    pieces[color][kind][square] = next_random(&mut state);
+                    square += 1;
+                }
+                kind += 1;
+            }
+            color += 1;
+        }
+        let side_to_move = next_random(&mut state);
+        let mut castling = [0; 16];
+        let mut rights = 1;
+        while rights < 16 {
+            castling[rights] = next_random(&mut state);
+            rights += 1;
+        }
+        let mut en_passant_file = [0; 8];
+        let mut file = 0;
+        while file < 8 {
+            en_passant_file[file] = next_random(&mut state);
+            file += 1;
+        }
+        Self {
+            pieces,
+            side_to_move,
+            castling,
+            en_passant_file,
+        }
+    }
+
+    #[inline(always)]
+    pub fn piece(&self, piece: Piece) -> u64 {
+        self.pieces[piece.color as usize][piece.kind as usize][piece.position.idx()]
+    }
+
+    #[inline(always)]
+    pub fn side_to_move(&self, color: Color) -> u64 {
+        match color {
+            Color::White => 0,
+            Color::Black => self.side_to_move,
+        }
+    }
+
+    #[inline(always)]
+    pub fn castling(&self, rights: CastlingRights) -> u64 {
+        self.castling[rights.bits() as usize]
+    }
+
+    // Only the file of the target square matters, the rank follows from the side to move
+    #[inline(always)]
+    pub fn en_passant(&self, target: Option<Bitboard>) -> u64 {
+        target.map_or(0, |target| self.en_passant_file[target.idx() % 8])
+    }
+}
+
+// Zobrist hashes are random already, so maps keyed by them can skip hashing the key again
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ZobristHasher(u64);
+
+impl Hasher for ZobristHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = self.0.rotate_left(8) ^ u64::from(byte);
+        }
+    }
+
+    fn write_u64(&mut self, hash: u64) {
+        self.0 = hash;
+    }
+}
+
+pub type ZobristMap<V> = HashMap<u64, V, BuildHasherDefault<ZobristHasher>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_distinct() {
+        let mut keys = ZOBRIST.pieces.as_flattened().as_flattened().to_vec();
+        keys.push(ZOBRIST.side_to_move);
+        keys.extend_from_slice(&ZOBRIST.castling[1..]);
+        keys.extend_from_slice(&ZOBRIST.en_passant_file);
+        let total = keys.len();
+        keys.sort_unstable();
+        keys.dedup();
+        assert_eq!(keys.len(), total);
+        assert!(!keys.contains(&0));
+    }
+}